@@ -0,0 +1,40 @@
+//! Times plate growth and merging at increasing subdivision levels.
+//!
+//! Run with `cargo bench --bench plates`. Both should take roughly four times as long for
+//! every level, as the number of tiles grows.
+
+extern crate terragen;
+
+use terragen::stopwatch::Stopwatch;
+use terragen::Generator;
+
+const LEVELS: [u32; 4] = [5, 6, 7, 8];
+const RUNS: u32 = 3;
+
+fn main() {
+    println!("{:>5} {:>8} {:>10} {:>10}", "level", "tiles", "grow ms", "merge ms");
+    for level in LEVELS.iter() {
+        let mut gen = Generator::new(1);
+        for _ in 0..*level {
+            gen.subdivide();
+        }
+        let mut planet = gen.to_planet();
+
+        let (mut grow_ms, mut merge_ms) = (0, 0);
+        for _ in 0..RUNS {
+            let sw = Stopwatch::start_new();
+            planet.grow_plates();
+            grow_ms += sw.elapsed_ms();
+
+            let sw = Stopwatch::start_new();
+            planet.merge_plates();
+            merge_ms += sw.elapsed_ms();
+        }
+
+        println!("{:>5} {:>8} {:>10} {:>10}",
+                 level,
+                 planet.num_tiles(),
+                 grow_ms / RUNS as i64,
+                 merge_ms / RUNS as i64);
+    }
+}
@@ -0,0 +1,322 @@
+use clap::ArgMatches;
+use terragen::stopwatch::Stopwatch;
+
+use terragen::Planet;
+use terragen::terrain::planet::{ClimateConfig, Simulation, BIOMES};
+use terragen::export::{self, ColourRamp, CubeChannel, CubeMap, CUBE_FACES, GltfColouring,
+                       Heightmap, ObjGrouping};
+
+use std::f32;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use {build_generator, parse_arg, parse_biome_rules, parse_climate_config, parse_erosion_config,
+     parse_plate_config, parse_sea_level};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Csv,
+    Planet,
+    Obj,
+    Glb,
+    Pgm,
+    Png,
+}
+
+impl OutputFormat {
+    fn from_path(path: &Path) -> Result<OutputFormat, String> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Ok(OutputFormat::Csv),
+            Some("planet") => Ok(OutputFormat::Planet),
+            Some("obj") => Ok(OutputFormat::Obj),
+            Some("glb") => Ok(OutputFormat::Glb),
+            Some("pgm") => Ok(OutputFormat::Pgm),
+            Some("png") => Ok(OutputFormat::Png),
+            _ => Err(format!("Unsupported output format: {}", path.display())),
+        }
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let output = Path::new(matches.value_of("output").unwrap());
+    let format = OutputFormat::from_path(output)?;
+    let options = ExportOptions::from_matches(matches)?;
+    let plate_config = parse_plate_config(matches)?;
+    let steps = parse_arg(matches, "steps", 0u32)?;
+    let erosion = parse_erosion_config(matches)?;
+    let climate = parse_climate_config(matches, ClimateConfig::default())?;
+    let biome_rules = parse_biome_rules(matches)?;
+
+    let sw = Stopwatch::start_new();
+    let generator = build_generator(matches)?;
+    info!("Generated level {} topology in {} ms",
+          generator.current_level(),
+          sw.elapsed_ms());
+
+    let sw = Stopwatch::start_new();
+    let mut planet = generator.to_planet_with(plate_config);
+    info!("Grew {} plates over {} tiles in {} ms",
+          planet.num_plates(),
+          planet.num_tiles(),
+          sw.elapsed_ms());
+
+    let sw = Stopwatch::start_new();
+    for merge in planet.merge_plates() {
+        debug!("Merged plate {} of {} tiles into plate {} of {} tiles",
+               merge.absorbed,
+               merge.absorbed_tiles,
+               merge.into,
+               merge.into_tiles);
+    }
+    info!("Merged plates down to {} in {} ms",
+          planet.num_plates(),
+          sw.elapsed_ms());
+
+    if steps > 0 {
+        let sw = Stopwatch::start_new();
+        let time_step = planet.default_time_step();
+        let mut simulation = Simulation::new(&planet, time_step);
+        for _ in 0..steps {
+            let report = simulation.advance(&mut planet);
+            debug!("At {}: {} tiles transferred, {} created, {} consumed, {} plates gone",
+                   simulation.time(),
+                   report.transferred,
+                   report.created,
+                   report.consumed,
+                   report.vanished);
+        }
+        info!("Moved plates for {} steps of {} in {} ms, {} plates left",
+              steps,
+              time_step,
+              sw.elapsed_ms(),
+              planet.num_plates());
+    }
+
+    // Before erosion, which builds up the coasts where rivers reach the sea
+    let sea_level = parse_sea_level(matches, &planet)?;
+    planet.set_sea_level(sea_level);
+
+    if erosion.iterations > 0 {
+        let sw = Stopwatch::start_new();
+        planet.erode(&erosion);
+        info!("Eroded terrain for {} iterations in {} ms",
+              erosion.iterations,
+              sw.elapsed_ms());
+    }
+
+    let sw = Stopwatch::start_new();
+    planet.set_surface(sea_level, climate, biome_rules);
+    info!("Set sea level to {:.0} m, leaving {:.1}% of the tiles dry, in {} ms",
+          planet.sea_level(),
+          planet.land_fraction() * 100.0,
+          sw.elapsed_ms());
+
+    let (coldest, warmest) = planet.tiles_iter()
+        .map(|t| planet.tile_temperature(t))
+        .fold((f32::MAX, f32::MIN), |(lo, hi), t| (lo.min(t), hi.max(t)));
+    info!("Temperatures range from {:.1} to {:.1} °C", coldest, warmest);
+    let (coldest, warmest) = planet.tiles_iter()
+        .filter(|t| planet.is_ocean(t))
+        .map(|t| planet.tile_sea_temperature(t))
+        .fold((f32::MAX, f32::MIN), |(lo, hi), t| (lo.min(t), hi.max(t)));
+    info!("Sea surface temperatures range from {:.1} to {:.1} °C", coldest, warmest);
+    let land: Vec<f32> = planet.tiles_iter()
+        .filter(|t| !planet.is_ocean(t))
+        .map(|t| planet.tile_precipitation(t))
+        .collect();
+    if !land.is_empty() {
+        info!("Precipitation on land averages {:.0} mm a year",
+              land.iter().sum::<f32>() / land.len() as f32);
+    }
+
+    let mut counts = vec![0; BIOMES.len()];
+    let mut unclassified = 0;
+    for tile in planet.tiles_iter() {
+        match planet.tile_biome(tile) {
+            Some(biome) => counts[biome.index()] += 1,
+            None => unclassified += 1,
+        }
+    }
+    for (biome, count) in BIOMES.iter().zip(counts.iter()).filter(|&(_, c)| *c > 0) {
+        debug!("{} tiles of {}", count, biome.name());
+    }
+    if unclassified > 0 {
+        warn!("No biome rule matches {} tiles", unclassified);
+    }
+
+    let endorheic = planet.water_bodies().iter().filter(|w| w.is_endorheic()).count();
+    info!("Found {} lakes and {} endorheic basins",
+          planet.water_bodies().len() - endorheic,
+          endorheic);
+
+    match options.cube_size {
+        Some(size) if format == OutputFormat::Png => {
+            write_cube_map(&planet, size, &options, output)?
+        }
+        _ => {
+            write_output(&planet, format, &options, output)
+                .map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+            info!("Wrote {}", output.display());
+        }
+    }
+
+    Ok(())
+}
+
+struct ExportOptions {
+    obj_grouping: ObjGrouping,
+    glb_colouring: GltfColouring,
+    map_size: (u32, u32),
+    colour_map: Option<PathBuf>,
+    cube_size: Option<u32>,
+    cube_channel: CubeChannel,
+    cube_atlas: bool,
+    ramp: ColourRamp,
+}
+
+impl ExportOptions {
+    fn from_matches(matches: &ArgMatches) -> Result<ExportOptions, String> {
+        let obj_grouping = match matches.value_of("obj-groups") {
+            None | Some("tile") => ObjGrouping::Tile,
+            Some("plate") => ObjGrouping::Plate,
+            Some(other) => return Err(format!("Invalid value for --obj-groups: {}", other)),
+        };
+        let glb_colouring = match matches.value_of("glb-colours") {
+            None | Some("elevation") => GltfColouring::Elevation,
+            Some("plate") => GltfColouring::Plate,
+            Some("temperature") => GltfColouring::Temperature,
+            Some("biome") => GltfColouring::Biome,
+            Some(other) => return Err(format!("Invalid value for --glb-colours: {}", other)),
+        };
+        let map_size = match matches.value_of("map-size") {
+            Some(size) => parse_size(size)?,
+            None => (2048, 1024),
+        };
+        let cube_size = match matches.value_of("cube-size") {
+            Some(_) => {
+                let size = parse_arg(matches, "cube-size", 0u32)?;
+                if size == 0 {
+                    return Err("Cube face size must be at least 1 pixel".to_string());
+                }
+                Some(size)
+            }
+            None => None,
+        };
+        let cube_channel = match matches.value_of("cube-channel") {
+            None | Some("elevation") => CubeChannel::Elevation,
+            Some("colour") => CubeChannel::Colour,
+            Some("plate") => CubeChannel::Plate,
+            Some("biome") => CubeChannel::Biome,
+            Some(other) => return Err(format!("Invalid value for --cube-channel: {}", other)),
+        };
+        let ramp = match matches.value_of("ramp") {
+            Some(path) => {
+                ColourRamp::from_png(path)
+                    .map_err(|e| format!("Failed to read colour ramp {}: {}", path, e))?
+            }
+            None => ColourRamp::elevation(),
+        };
+        Ok(ExportOptions {
+            obj_grouping,
+            glb_colouring,
+            map_size,
+            colour_map: matches.value_of("colour-map").map(PathBuf::from),
+            cube_size,
+            cube_channel,
+            cube_atlas: matches.is_present("cube-atlas"),
+            ramp,
+        })
+    }
+}
+
+fn write_output(planet: &Planet,
+                format: OutputFormat,
+                options: &ExportOptions,
+                path: &Path)
+                -> ::std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        OutputFormat::Csv => export::write_tiles_csv(planet, &mut out)?,
+        OutputFormat::Planet => planet.save(&mut out)?,
+        OutputFormat::Obj => {
+            // Materials go next to the OBJ, which refers to them by file name
+            let mtl_path = path.with_extension("mtl");
+            let mtl_name = mtl_path.file_name().and_then(|n| n.to_str());
+            export::write_obj(planet, options.obj_grouping, &options.ramp, mtl_name, &mut out)?;
+
+            let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+            export::write_mtl(&options.ramp, &mut mtl)?;
+            mtl.flush()?;
+        }
+        OutputFormat::Glb => {
+            export::write_glb(planet, options.glb_colouring, &options.ramp, &mut out)?
+        }
+        OutputFormat::Pgm | OutputFormat::Png => {
+            let (width, height) = options.map_size;
+            let map = Heightmap::equirectangular(planet, width, height);
+            if format == OutputFormat::Pgm {
+                map.write_pgm(&mut out)?;
+            } else {
+                map.write_png(&mut out)?;
+            }
+
+            if let Some(ref colour_path) = options.colour_map {
+                let mut colour = BufWriter::new(File::create(colour_path)?);
+                map.write_colour_png(&options.ramp, &mut colour)?;
+                colour.flush()?;
+                info!("Wrote {}", colour_path.display());
+            }
+        }
+    }
+    out.flush()
+}
+
+/// Writes the faces to `<stem>_<face>.png` next to `path`, or all of them to `path` itself
+/// as an atlas.
+fn write_cube_map(planet: &Planet,
+                  size: u32,
+                  options: &ExportOptions,
+                  path: &Path)
+                  -> Result<(), String> {
+    let sw = Stopwatch::start_new();
+    let cube = CubeMap::new(planet, size);
+    info!("Sampled {} by {} cube map faces in {} ms", size, size, sw.elapsed_ms());
+
+    let write = |target: &Path, face| -> Result<(), String> {
+        let result = File::create(target).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            match face {
+                Some(face) => {
+                    cube.write_face(planet, face, options.cube_channel, &options.ramp, &mut out)?
+                }
+                None => cube.write_atlas(planet, options.cube_channel, &options.ramp, &mut out)?,
+            }
+            out.flush()
+        });
+        result.map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+        info!("Wrote {}", target.display());
+        Ok(())
+    };
+
+    if options.cube_atlas {
+        write(path, None)
+    } else {
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("cube");
+        for face in CUBE_FACES.iter() {
+            write(&path.with_file_name(format!("{}_{}.png", stem, face.name())), Some(*face))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid value for --map-size: {}, expected WIDTHxHEIGHT", size);
+    let mut parts = size.splitn(2, 'x');
+    let width = parts.next().and_then(|w| w.parse::<u32>().ok()).ok_or_else(&invalid)?;
+    let height = parts.next().and_then(|h| h.parse::<u32>().ok()).ok_or_else(&invalid)?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
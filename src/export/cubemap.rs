@@ -0,0 +1,254 @@
+use export::ramp::ColourRamp;
+use export::raster::{elevation_to_u16, encode_png};
+use mesh::elevation_colour;
+use terrain::planet::{Planet, TileIndex};
+use terrain::types::Vertex;
+
+use png::{BitDepth, ColorType};
+
+use std::io::{self, Write};
+
+/// Faces in the order and orientation of OpenGL cube map targets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+pub const CUBE_FACES: [CubeFace; 6] = [CubeFace::PositiveX,
+                                       CubeFace::NegativeX,
+                                       CubeFace::PositiveY,
+                                       CubeFace::NegativeY,
+                                       CubeFace::PositiveZ,
+                                       CubeFace::NegativeZ];
+
+impl CubeFace {
+    /// Short name used in file names, `px`, `nx`, `py` and so on.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CubeFace::PositiveX => "px",
+            CubeFace::NegativeX => "nx",
+            CubeFace::PositiveY => "py",
+            CubeFace::NegativeY => "ny",
+            CubeFace::PositiveZ => "pz",
+            CubeFace::NegativeZ => "nz",
+        }
+    }
+
+    /// Direction through the point (s, t) of the face, both in [-1, 1] with t growing
+    /// downwards in the image.
+    pub fn direction(&self, s: f32, t: f32) -> Vertex {
+        match *self {
+            CubeFace::PositiveX => Vertex::new(1.0, -t, -s),
+            CubeFace::NegativeX => Vertex::new(-1.0, -t, s),
+            CubeFace::PositiveY => Vertex::new(s, 1.0, t),
+            CubeFace::NegativeY => Vertex::new(s, -1.0, -t),
+            CubeFace::PositiveZ => Vertex::new(s, -t, 1.0),
+            CubeFace::NegativeZ => Vertex::new(-s, -t, -1.0),
+        }
+    }
+}
+
+/// Tile data written to each texel of a cube map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CubeChannel {
+    /// 16-bit greyscale, scaled like `Heightmap::to_u16`.
+    Elevation,
+    /// 8-bit RGB from the elevation ramp.
+    Colour,
+    /// 16-bit greyscale plate id.
+    Plate,
+    /// 8-bit greyscale position of the biome in `BIOMES`, 255 where no rule matches.
+    Biome,
+}
+
+/// The tile under every texel of six square faces of `size` by `size` texels.
+pub struct CubeMap {
+    pub size: u32,
+    tiles: Vec<TileIndex>,
+}
+
+impl CubeMap {
+    pub fn new(planet: &Planet, size: u32) -> CubeMap {
+        let mut tiles = Vec::with_capacity((6 * size * size) as usize);
+        for face in CUBE_FACES.iter() {
+            for y in 0..size {
+                let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                for x in 0..size {
+                    let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                    tiles.push(planet.tile_at(&face.direction(s, t)));
+                }
+            }
+        }
+        CubeMap {
+            size,
+            tiles,
+        }
+    }
+
+    /// Writes one face as a PNG.
+    pub fn write_face<W: Write>(&self,
+                                planet: &Planet,
+                                face: CubeFace,
+                                channel: CubeChannel,
+                                ramp: &ColourRamp,
+                                out: &mut W)
+                                -> io::Result<()> {
+        let face_len = (self.size * self.size) as usize;
+        let i = CUBE_FACES.iter().position(|f| *f == face).unwrap();
+        let data = texels(planet, &self.tiles[i * face_len..(i + 1) * face_len], channel, ramp);
+        let (colour_type, bit_depth) = format(channel);
+        encode_png(out, self.size, self.size, colour_type, bit_depth, &data)
+    }
+
+    /// Writes all faces side by side as a single PNG strip, in the order of `CUBE_FACES`.
+    pub fn write_atlas<W: Write>(&self,
+                                 planet: &Planet,
+                                 channel: CubeChannel,
+                                 ramp: &ColourRamp,
+                                 out: &mut W)
+                                 -> io::Result<()> {
+        let size = self.size as usize;
+        let mut strip = Vec::with_capacity(self.tiles.len());
+        for y in 0..size {
+            for face in 0..6 {
+                let row = (face * size + y) * size;
+                strip.extend_from_slice(&self.tiles[row..row + size]);
+            }
+        }
+        let data = texels(planet, &strip, channel, ramp);
+        let (colour_type, bit_depth) = format(channel);
+        encode_png(out, self.size * 6, self.size, colour_type, bit_depth, &data)
+    }
+}
+
+fn format(channel: CubeChannel) -> (ColorType, BitDepth) {
+    match channel {
+        CubeChannel::Elevation | CubeChannel::Plate => (ColorType::Grayscale, BitDepth::Sixteen),
+        CubeChannel::Colour => (ColorType::RGB, BitDepth::Eight),
+        CubeChannel::Biome => (ColorType::Grayscale, BitDepth::Eight),
+    }
+}
+
+fn texels(planet: &Planet,
+          tiles: &[TileIndex],
+          channel: CubeChannel,
+          ramp: &ColourRamp)
+          -> Vec<u8> {
+    let (lower_scale, upper_scale) = planet.get_elevation_scale();
+    let sea_level = planet.sea_level();
+    let mut data = Vec::with_capacity(tiles.len() * 3);
+    for t in tiles.iter() {
+        let tile = planet.tile(*t);
+        match channel {
+            CubeChannel::Elevation => {
+                let e = planet.tile_elevation(tile) - sea_level;
+                let h = elevation_to_u16(e, lower_scale, upper_scale);
+                data.push((h >> 8) as u8);
+                data.push(h as u8);
+            }
+            CubeChannel::Colour => {
+                let e = planet.tile_elevation(tile) - sea_level;
+                data.extend_from_slice(&ramp.sample(elevation_colour(e, lower_scale, upper_scale)));
+            }
+            CubeChannel::Plate => {
+                data.push((tile.plate_id >> 8) as u8);
+                data.push(tile.plate_id as u8);
+            }
+            CubeChannel::Biome => {
+                data.push(planet.tile_biome(tile).map(|b| b.index() as u8).unwrap_or(255));
+            }
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::generator::Generator;
+    use terrain::planet::BIOMES;
+
+    use png;
+    use png::HasParameters;
+
+    fn planet() -> Planet {
+        let mut gen = Generator::new(8);
+        gen.subdivide();
+        gen.subdivide();
+        let mut planet = gen.to_planet();
+        planet.update_surface();
+        planet
+    }
+
+    #[test]
+    fn face_centres_point_along_axes() {
+        assert_eq!(CubeFace::PositiveX.direction(0.0, 0.0), Vertex::new(1.0, 0.0, 0.0));
+        assert_eq!(CubeFace::NegativeY.direction(0.0, 0.0), Vertex::new(0.0, -1.0, 0.0));
+        assert_eq!(CubeFace::NegativeZ.direction(0.0, 0.0), Vertex::new(0.0, 0.0, -1.0));
+        // Neighbouring faces meet along their edges
+        assert_eq!(CubeFace::PositiveX.direction(1.0, 0.0),
+                   CubeFace::NegativeZ.direction(-1.0, 0.0));
+        assert_eq!(CubeFace::PositiveY.direction(0.0, 1.0),
+                   CubeFace::PositiveZ.direction(0.0, -1.0));
+    }
+
+    /// Decodes a PNG as stored, keeping 16-bit samples as two bytes each.
+    fn decode(data: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set(png::Transformations::IDENTITY);
+        let (info, mut reader) = decoder.read_info().unwrap();
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        (info.width, info.height, pixels)
+    }
+
+    #[test]
+    fn atlas_rows_are_face_rows() {
+        let planet = planet();
+        let cube = CubeMap::new(&planet, 8);
+        let ramp = ColourRamp::elevation();
+
+        let mut atlas = Vec::new();
+        cube.write_atlas(&planet, CubeChannel::Plate, &ramp, &mut atlas).unwrap();
+        let (width, height, atlas_data) = decode(&atlas);
+        assert_eq!((width, height), (48, 8));
+
+        let mut face = Vec::new();
+        cube.write_face(&planet, CubeFace::PositiveZ, CubeChannel::Plate, &ramp, &mut face)
+            .unwrap();
+        let (_, _, face_data) = decode(&face);
+        assert!(face_data.iter().any(|b| *b != 0));
+
+        // Plate ids are 16-bit, two bytes per texel
+        for y in 0..8 {
+            assert_eq!(&atlas_data[y * 48 * 2 + 32 * 2..y * 48 * 2 + 40 * 2],
+                       &face_data[y * 8 * 2..(y + 1) * 8 * 2]);
+        }
+    }
+
+    #[test]
+    fn biome_texels_are_biome_indices() {
+        let planet = planet();
+        let cube = CubeMap::new(&planet, 8);
+
+        let mut face = Vec::new();
+        cube.write_face(&planet,
+                        CubeFace::PositiveX,
+                        CubeChannel::Biome,
+                        &ColourRamp::elevation(),
+                        &mut face)
+            .unwrap();
+        let (info, mut reader) = png::Decoder::new(&face[..]).read_info().unwrap();
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+
+        assert_eq!(data.len(), 64);
+        let centre = planet.tile(planet.tile_at(&CubeFace::PositiveX.direction(0.125, 0.125)));
+        assert_eq!(Some(BIOMES[data[4 * 8 + 4] as usize]), planet.tile_biome(centre));
+    }
+}
@@ -0,0 +1,338 @@
+use export::ramp::ColourRamp;
+use mesh::{generate_dual, DualColouring};
+use terrain::planet::Planet;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use std::f32;
+use std::io::{self, Write};
+
+const GLB_MAGIC: u32 = 0x46546C67;
+const CHUNK_JSON: u32 = 0x4E4F534A;
+const CHUNK_BIN: u32 = 0x004E4942;
+
+const UNSIGNED_BYTE: u32 = 5121;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// What the `COLOR_0` vertex colours of an exported planet show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GltfColouring {
+    Elevation,
+    Plate,
+    Temperature,
+    Biome,
+}
+
+/// Writes the dual mesh as a binary glTF 2.0 file.
+///
+/// Besides positions, normals and vertex colours, every vertex carries the `_TILE_ID`,
+/// `_PLATE_ID`, `_ELEVATION` and `_MOVEMENT` of its tile. The mesh `extras` also point out
+/// accessors holding the plate id, elevation and movement vector indexed by tile id, so
+/// a tile picked from a rendered triangle can be looked up without walking its vertices.
+/// Elevation colours come from `ramp`, plate, temperature and biome colours from the built
+/// in group palette, temperature ramp and biome palette.
+pub fn write_glb<W: Write>(planet: &Planet,
+                           colouring: GltfColouring,
+                           ramp: &ColourRamp,
+                           out: &mut W)
+                           -> io::Result<()> {
+    let (mesh, palette) = match colouring {
+        GltfColouring::Elevation => {
+            (generate_dual(planet, false, DualColouring::Elevation), ramp.clone())
+        }
+        GltfColouring::Plate => {
+            (generate_dual(planet, false, DualColouring::Plates), ColourRamp::groups())
+        }
+        GltfColouring::Temperature => {
+            (generate_dual(planet, false, DualColouring::Temperature), ColourRamp::temperature())
+        }
+        GltfColouring::Biome => {
+            (generate_dual(planet, false, DualColouring::Biomes), ColourRamp::biomes())
+        }
+    };
+
+    let mut tile_ids = Vec::with_capacity(mesh.vertices.len());
+    let mut tile_plates = Vec::with_capacity(planet.num_tiles());
+    let mut tile_elevations = Vec::with_capacity(planet.num_tiles());
+    let mut tile_movements = Vec::with_capacity(planet.num_tiles());
+    for (idx, tile) in planet.tiles_iter().enumerate() {
+        for _ in 0..tile.num_vertices() + 1 {
+            tile_ids.push(idx);
+        }
+        tile_plates.push(tile.plate_id);
+        tile_elevations.push(planet.tile_elevation(tile));
+        tile_movements.push(tile.movement_vector.clone());
+    }
+
+    let mut bin = BinBuilder::new();
+
+    let mut lo = [f32::MAX; 3];
+    let mut hi = [f32::MIN; 3];
+    for v in mesh.vertices.iter() {
+        for (i, c) in [v.x, v.y, v.z].iter().enumerate() {
+            lo[i] = lo[i].min(*c);
+            hi[i] = hi[i].max(*c);
+        }
+    }
+    let mut data = Vec::new();
+    for v in mesh.vertices.iter() {
+        write_vec3(&mut data, v.x, v.y, v.z)?;
+    }
+    let position = bin.accessor(ARRAY_BUFFER, FLOAT, "VEC3", Some((lo, hi)), data);
+
+    let mut data = Vec::new();
+    for n in mesh.normals.iter() {
+        write_vec3(&mut data, n.x, n.y, n.z)?;
+    }
+    let normal = bin.accessor(ARRAY_BUFFER, FLOAT, "VEC3", None, data);
+
+    let mut data = Vec::new();
+    for &(u, _) in mesh.texcoords.iter() {
+        let c = palette.sample(u);
+        data.extend_from_slice(&[c[0], c[1], c[2], 255]);
+    }
+    let colour = bin.accessor(ARRAY_BUFFER, UNSIGNED_BYTE, "VEC4", None, data);
+
+    // Custom vertex attributes can not be integers, plain floats hold ids up to 2^24 exactly
+    let mut data = Vec::new();
+    for &t in tile_ids.iter() {
+        data.write_f32::<LittleEndian>(t as f32)?;
+    }
+    let tile_id = bin.accessor(ARRAY_BUFFER, FLOAT, "SCALAR", None, data);
+
+    let mut data = Vec::new();
+    for &t in tile_ids.iter() {
+        data.write_f32::<LittleEndian>(tile_plates[t] as f32)?;
+    }
+    let plate_id = bin.accessor(ARRAY_BUFFER, FLOAT, "SCALAR", None, data);
+
+    let mut data = Vec::new();
+    for &t in tile_ids.iter() {
+        data.write_f32::<LittleEndian>(tile_elevations[t])?;
+    }
+    let elevation = bin.accessor(ARRAY_BUFFER, FLOAT, "SCALAR", None, data);
+
+    let mut data = Vec::new();
+    for &t in tile_ids.iter() {
+        let m = &tile_movements[t];
+        write_vec3(&mut data, m.x, m.y, m.z)?;
+    }
+    let movement = bin.accessor(ARRAY_BUFFER, FLOAT, "VEC3", None, data);
+
+    let mut data = Vec::new();
+    for f in mesh.faces.iter() {
+        for i in 0..3 {
+            data.write_u32::<LittleEndian>(f[i])?;
+        }
+    }
+    let indices = bin.accessor(ELEMENT_ARRAY_BUFFER, UNSIGNED_INT, "SCALAR", None, data);
+
+    let mut data = Vec::new();
+    for &p in tile_plates.iter() {
+        data.write_u32::<LittleEndian>(p)?;
+    }
+    let plate_table = bin.table(UNSIGNED_INT, "SCALAR", data);
+
+    let mut data = Vec::new();
+    for &e in tile_elevations.iter() {
+        data.write_f32::<LittleEndian>(e)?;
+    }
+    let elevation_table = bin.table(FLOAT, "SCALAR", data);
+
+    let mut data = Vec::new();
+    for m in tile_movements.iter() {
+        write_vec3(&mut data, m.x, m.y, m.z)?;
+    }
+    let movement_table = bin.table(FLOAT, "VEC3", data);
+
+    let json = format!("{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"terragen\"}},\
+                        \"scene\":0,\"scenes\":[{{\"nodes\":[0]}}],\
+                        \"nodes\":[{{\"mesh\":0,\"name\":\"planet\"}}],\
+                        \"meshes\":[{{\"name\":\"planet\",\"primitives\":[{{\
+                        \"attributes\":{{\"POSITION\":{},\"NORMAL\":{},\"COLOR_0\":{},\
+                        \"_TILE_ID\":{},\"_PLATE_ID\":{},\"_ELEVATION\":{},\"_MOVEMENT\":{}}},\
+                        \"indices\":{},\"mode\":4}}],\
+                        \"extras\":{{\"tiles\":{},\"plates\":{},\"colouring\":\"{}\",\
+                        \"tileAttributes\":{{\"plateId\":{},\"elevation\":{},\
+                        \"movement\":{}}}}}}}],\
+                        \"buffers\":[{{\"byteLength\":{}}}],\
+                        \"bufferViews\":[{}],\"accessors\":[{}]}}",
+                       position,
+                       normal,
+                       colour,
+                       tile_id,
+                       plate_id,
+                       elevation,
+                       movement,
+                       indices,
+                       planet.num_tiles(),
+                       planet.num_plates(),
+                       match colouring {
+                           GltfColouring::Elevation => "elevation",
+                           GltfColouring::Plate => "plate",
+                           GltfColouring::Temperature => "temperature",
+                           GltfColouring::Biome => "biome",
+                       },
+                       plate_table,
+                       elevation_table,
+                       movement_table,
+                       bin.data.len(),
+                       bin.views.join(","),
+                       bin.accessors.join(","));
+
+    let mut json = json.into_bytes();
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+
+    let total = 12 + 8 + json.len() + 8 + bin.data.len();
+    out.write_u32::<LittleEndian>(GLB_MAGIC)?;
+    out.write_u32::<LittleEndian>(2)?;
+    out.write_u32::<LittleEndian>(total as u32)?;
+    out.write_u32::<LittleEndian>(json.len() as u32)?;
+    out.write_u32::<LittleEndian>(CHUNK_JSON)?;
+    out.write_all(&json)?;
+    out.write_u32::<LittleEndian>(bin.data.len() as u32)?;
+    out.write_u32::<LittleEndian>(CHUNK_BIN)?;
+    out.write_all(&bin.data)
+}
+
+fn write_vec3<W: Write>(out: &mut W, x: f32, y: f32, z: f32) -> io::Result<()> {
+    out.write_f32::<LittleEndian>(x)?;
+    out.write_f32::<LittleEndian>(y)?;
+    out.write_f32::<LittleEndian>(z)
+}
+
+/// The binary chunk, with one buffer view per accessor, each aligned to four bytes.
+struct BinBuilder {
+    data: Vec<u8>,
+    views: Vec<String>,
+    accessors: Vec<String>,
+}
+
+impl BinBuilder {
+    fn new() -> BinBuilder {
+        BinBuilder {
+            data: Vec::new(),
+            views: Vec::new(),
+            accessors: Vec::new(),
+        }
+    }
+
+    /// Adds a vertex attribute or index accessor over `data`, returning its index.
+    fn accessor(&mut self,
+                target: u32,
+                component_type: u32,
+                kind: &str,
+                bounds: Option<([f32; 3], [f32; 3])>,
+                data: Vec<u8>)
+                -> usize {
+        let count = data.len() / element_size(component_type, kind);
+        let view = self.view(Some(target), data);
+        let normalized = if component_type == UNSIGNED_BYTE {
+            ",\"normalized\":true"
+        } else {
+            ""
+        };
+        let bounds = match bounds {
+            Some((lo, hi)) => {
+                format!(",\"min\":[{},{},{}],\"max\":[{},{},{}]",
+                        lo[0],
+                        lo[1],
+                        lo[2],
+                        hi[0],
+                        hi[1],
+                        hi[2])
+            }
+            None => String::new(),
+        };
+        self.accessors.push(format!("{{\"bufferView\":{},\"componentType\":{},\
+                                     \"count\":{},\"type\":\"{}\"{}{}}}",
+                                    view,
+                                    component_type,
+                                    count,
+                                    kind,
+                                    normalized,
+                                    bounds));
+        self.accessors.len() - 1
+    }
+
+    /// Adds an accessor that is not bound to the mesh, for per tile lookup tables.
+    fn table(&mut self, component_type: u32, kind: &str, data: Vec<u8>) -> usize {
+        let count = data.len() / element_size(component_type, kind);
+        let view = self.view(None, data);
+        self.accessors.push(format!("{{\"bufferView\":{},\"componentType\":{},\
+                                     \"count\":{},\"type\":\"{}\"}}",
+                                    view,
+                                    component_type,
+                                    count,
+                                    kind));
+        self.accessors.len() - 1
+    }
+
+    fn view(&mut self, target: Option<u32>, data: Vec<u8>) -> usize {
+        let offset = self.data.len();
+        self.data.extend_from_slice(&data);
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0);
+        }
+        let target = match target {
+            Some(t) => format!(",\"target\":{}", t),
+            None => String::new(),
+        };
+        self.views.push(format!("{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{}{}}}",
+                                offset,
+                                data.len(),
+                                target));
+        self.views.len() - 1
+    }
+}
+
+fn element_size(component_type: u32, kind: &str) -> usize {
+    let component_size = if component_type == UNSIGNED_BYTE { 1 } else { 4 };
+    let components = match kind {
+        "VEC3" => 3,
+        "VEC4" => 4,
+        _ => 1,
+    };
+    component_size * components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::generator::Generator;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    #[test]
+    fn glb_chunks_are_consistent() {
+        let mut gen = Generator::new(12);
+        gen.subdivide();
+        let mut planet = gen.to_planet();
+        planet.merge_plates();
+
+        let mut out = Vec::new();
+        write_glb(&planet, GltfColouring::Plate, &ColourRamp::elevation(), &mut out).unwrap();
+
+        assert_eq!(LittleEndian::read_u32(&out[0..4]), GLB_MAGIC);
+        assert_eq!(LittleEndian::read_u32(&out[8..12]) as usize, out.len());
+        let json_len = LittleEndian::read_u32(&out[12..16]) as usize;
+        assert_eq!(json_len % 4, 0);
+        assert_eq!(LittleEndian::read_u32(&out[16..20]), CHUNK_JSON);
+        let json = ::std::str::from_utf8(&out[20..20 + json_len]).unwrap();
+        let bin_len = LittleEndian::read_u32(&out[20 + json_len..24 + json_len]) as usize;
+        assert_eq!(LittleEndian::read_u32(&out[24 + json_len..28 + json_len]), CHUNK_BIN);
+        assert_eq!(28 + json_len + bin_len, out.len());
+
+        assert!(json.contains(&format!("\"byteLength\":{}}}", bin_len)));
+        for name in ["COLOR_0", "_TILE_ID", "_PLATE_ID", "_ELEVATION", "_MOVEMENT"].iter() {
+            assert!(json.contains(name), "Missing attribute {}", name);
+        }
+        assert!(json.contains(&format!("\"tiles\":{}", planet.num_tiles())));
+    }
+}
@@ -0,0 +1,13 @@
+mod cubemap;
+mod gltf;
+mod obj;
+mod ramp;
+mod raster;
+mod tiles;
+
+pub use export::cubemap::{CubeChannel, CubeFace, CubeMap, CUBE_FACES};
+pub use export::gltf::{GltfColouring, write_glb};
+pub use export::obj::{ObjGrouping, write_obj, write_mtl};
+pub use export::ramp::ColourRamp;
+pub use export::raster::Heightmap;
+pub use export::tiles::write_tiles_csv;
@@ -0,0 +1,181 @@
+use export::ramp::ColourRamp;
+use mesh::elevation_colour;
+use terrain::planet::Planet;
+
+use std::io::{self, Write};
+
+/// How faces of the dual mesh are split into OBJ groups.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjGrouping {
+    /// One group per tile, `tile_<index>`.
+    Tile,
+    /// One group per plate, `plate_<id>`.
+    Plate,
+}
+
+/// Writes the dual mesh as a Wavefront OBJ, with each tile as a polygon fan around its
+/// midpoint. Every tile uses the material for its colour on the elevation ramp, as written
+/// by `write_mtl`, which is referenced through `mtl_name` if given.
+pub fn write_obj<W: Write>(planet: &Planet,
+                           grouping: ObjGrouping,
+                           ramp: &ColourRamp,
+                           mtl_name: Option<&str>,
+                           out: &mut W)
+                           -> io::Result<()> {
+    writeln!(out, "# terragen dual mesh, {} tiles", planet.num_tiles())?;
+    if let Some(name) = mtl_name {
+        writeln!(out, "mtllib {}", name)?;
+    }
+
+    // OBJ indices are 1-based, the first vertex of each tile is its midpoint
+    let mut first_vertex = Vec::with_capacity(planet.num_tiles());
+    let mut next_vertex = 1;
+    for tile in planet.tiles_iter() {
+        first_vertex.push(next_vertex);
+        let mp = planet.tile_midpoint(tile);
+        writeln!(out, "v {} {} {}", mp.x, mp.y, mp.z)?;
+        for v in planet.tile_border_points(tile).iter() {
+            writeln!(out, "v {} {} {}", v.x, v.y, v.z)?;
+        }
+        next_vertex += tile.num_vertices() + 1;
+    }
+    for tile in planet.tiles_iter() {
+        let n = planet.tile_normal(tile);
+        writeln!(out, "vn {} {} {}", n.x, n.y, n.z)?;
+    }
+
+    let tiles: Vec<_> = planet.tiles_iter().collect();
+    let mut order: Vec<usize> = (0..tiles.len()).collect();
+    if grouping == ObjGrouping::Plate {
+        order.sort_by_key(|&i| tiles[i].plate_id);
+    }
+
+    let (lower_scale, upper_scale) = planet.get_elevation_scale();
+    let mut current_group = None;
+    let mut current_material = None;
+    for &idx in order.iter() {
+        let tile = tiles[idx];
+
+        let group = match grouping {
+            ObjGrouping::Tile => idx,
+            ObjGrouping::Plate => tile.plate_id as usize,
+        };
+        if current_group != Some(group) {
+            match grouping {
+                ObjGrouping::Tile => writeln!(out, "g tile_{}", group)?,
+                ObjGrouping::Plate => writeln!(out, "g plate_{}", group)?,
+            }
+            current_group = Some(group);
+        }
+
+        let colour = elevation_colour(planet.tile_elevation(tile) - planet.sea_level(),
+                                      lower_scale,
+                                      upper_scale);
+        let material = ramp.index(colour);
+        if current_material != Some(material) {
+            writeln!(out, "usemtl {}", material_name(material))?;
+            current_material = Some(material);
+        }
+
+        let center = first_vertex[idx];
+        let normal = idx + 1;
+        let n = tile.num_vertices();
+        for j in 0..n {
+            let p1 = center + 1 + j;
+            let p2 = center + 1 + (j + 1) % n;
+            writeln!(out,
+                     "f {}//{} {}//{} {}//{}",
+                     center,
+                     normal,
+                     p1,
+                     normal,
+                     p2,
+                     normal)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes one diffuse material per colour of the ramp, for use with `write_obj`.
+pub fn write_mtl<W: Write>(ramp: &ColourRamp, out: &mut W) -> io::Result<()> {
+    writeln!(out, "# terragen elevation ramp, {} colours", ramp.len())?;
+    for i in 0..ramp.len() {
+        let c = ramp.colour(i);
+        writeln!(out)?;
+        writeln!(out, "newmtl {}", material_name(i))?;
+        writeln!(out,
+                 "Kd {:.4} {:.4} {:.4}",
+                 c[0] as f32 / 255.0,
+                 c[1] as f32 / 255.0,
+                 c[2] as f32 / 255.0)?;
+        writeln!(out, "Ka 0.0000 0.0000 0.0000")?;
+        writeln!(out, "Ks 0.0000 0.0000 0.0000")?;
+        writeln!(out, "illum 1")?;
+    }
+    Ok(())
+}
+
+fn material_name(index: usize) -> String {
+    format!("elevation_{:03}", index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::generator::Generator;
+
+    fn exported(grouping: ObjGrouping) -> (Planet, String) {
+        let mut gen = Generator::new(9);
+        gen.subdivide();
+        let mut planet = gen.to_planet();
+        planet.merge_plates();
+
+        let mut out = Vec::new();
+        write_obj(&planet,
+                  grouping,
+                  &ColourRamp::elevation(),
+                  Some("planet.mtl"),
+                  &mut out)
+            .unwrap();
+        (planet, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn faces_refer_to_written_vertices_and_materials() {
+        let (planet, text) = exported(ObjGrouping::Tile);
+
+        let mut mtl = Vec::new();
+        write_mtl(&ColourRamp::elevation(), &mut mtl).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+
+        let num_vertices = text.lines().filter(|l| l.starts_with("v ")).count();
+        let num_normals = text.lines().filter(|l| l.starts_with("vn ")).count();
+        assert_eq!(num_normals, planet.num_tiles());
+        for line in text.lines() {
+            if let Some(corners) = line.strip_prefix("f ") {
+                for corner in corners.split(' ') {
+                    let mut parts = corner.split("//");
+                    let v: usize = parts.next().unwrap().parse().unwrap();
+                    let n: usize = parts.next().unwrap().parse().unwrap();
+                    assert!(v >= 1 && v <= num_vertices);
+                    assert!(n >= 1 && n <= num_normals);
+                }
+            } else if let Some(material) = line.strip_prefix("usemtl ") {
+                assert!(mtl.contains(&format!("newmtl {}\n", material)));
+            }
+        }
+        assert_eq!(text.lines().filter(|l| l.starts_with("g tile_")).count(),
+                   planet.num_tiles());
+    }
+
+    #[test]
+    fn plate_grouping_writes_each_plate_once() {
+        let (planet, text) = exported(ObjGrouping::Plate);
+
+        let groups: Vec<&str> = text.lines().filter(|l| l.starts_with("g ")).collect();
+        assert_eq!(groups.len(), planet.num_plates());
+        let num_faces: usize = planet.tiles_iter().map(|t| t.num_vertices()).sum();
+        assert_eq!(text.lines().filter(|l| l.starts_with("f ")).count(), num_faces);
+    }
+}
@@ -0,0 +1,165 @@
+use png::{self, ColorType};
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+// Every eighth texel of the top row of media/elevation.png
+const ELEVATION: [[u8; 3]; 32] = [[108, 59, 23], [117, 64, 24], [128, 70, 26], [140, 77, 29],
+                                  [152, 83, 32], [167, 90, 35], [178, 97, 38], [188, 102, 39],
+                                  [193, 107, 41], [195, 111, 49], [197, 118, 58], [197, 125, 69],
+                                  [199, 131, 79], [201, 140, 91], [203, 147, 102], [206, 152, 109],
+                                  [58, 214, 255], [54, 209, 255], [47, 200, 255], [40, 187, 254],
+                                  [31, 175, 254], [22, 162, 255], [15, 152, 255], [8, 142, 255],
+                                  [4, 135, 254], [2, 131, 252], [3, 125, 247], [3, 117, 241],
+                                  [2, 107, 235], [0, 99, 230], [1, 93, 224], [0, 87, 220]];
+
+// The middle of each group in media/groups.png
+const GROUPS: [[u8; 3]; 16] = [[255, 255, 255], [0, 179, 224], [10, 123, 255], [96, 59, 192],
+                               [198, 69, 111], [255, 88, 23], [255, 129, 0], [255, 184, 0],
+                               [255, 207, 0], [255, 250, 80], [223, 235, 68], [134, 195, 81],
+                               [188, 226, 158], [163, 232, 255], [255, 181, 143], [234, 168, 255]];
+
+// Every eighth texel of the top row of media/temperature.png, from -40 °C to 40 °C
+const TEMPERATURE: [[u8; 3]; 32] = [[255, 255, 255], [241, 234, 255], [227, 212, 255],
+                                    [214, 191, 255], [200, 170, 255], [180, 147, 246],
+                                    [160, 124, 237], [139, 102, 229], [119, 80, 220],
+                                    [99, 80, 223], [79, 80, 225], [59, 80, 228], [40, 81, 230],
+                                    [45, 109, 233], [50, 137, 235], [55, 164, 238],
+                                    [60, 190, 238], [65, 193, 208], [70, 195, 178],
+                                    [75, 198, 148], [83, 201, 119], [123, 208, 104],
+                                    [163, 216, 89], [204, 223, 74], [240, 228, 59],
+                                    [243, 203, 49], [245, 177, 39], [248, 152, 29],
+                                    [248, 127, 20], [231, 99, 20], [213, 72, 20], [195, 44, 20]];
+
+// The middle of each group in media/biomes.png, in the order of `BIOMES` after a first
+// colour for tiles no rule matches
+const BIOMES: [[u8; 3]; 16] = [[255, 0, 255], [24, 62, 140], [44, 120, 196], [200, 225, 240],
+                               [250, 252, 255], [150, 160, 128], [62, 110, 84], [70, 142, 60],
+                               [36, 112, 74], [172, 200, 98], [232, 210, 150], [204, 186, 82],
+                               [118, 168, 48], [24, 122, 36], [255, 0, 255], [255, 0, 255]];
+
+/// Colours looked up by a texture coordinate in [0, 1], like the viewer samples its ramp
+/// textures. Lookup picks the nearest colour without blending, so hard edges such as the
+/// coast line stay sharp.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColourRamp {
+    colours: Vec<[u8; 3]>,
+}
+
+impl ColourRamp {
+    pub fn new(colours: Vec<[u8; 3]>) -> ColourRamp {
+        assert!(!colours.is_empty(), "A colour ramp needs at least one colour");
+        ColourRamp { colours }
+    }
+
+    /// Built in copy of `media/elevation.png`.
+    pub fn elevation() -> ColourRamp {
+        ColourRamp::new(ELEVATION.to_vec())
+    }
+
+    /// Built in copy of `media/groups.png`.
+    pub fn groups() -> ColourRamp {
+        ColourRamp::new(GROUPS.to_vec())
+    }
+
+    /// Built in copy of `media/temperature.png`.
+    pub fn temperature() -> ColourRamp {
+        ColourRamp::new(TEMPERATURE.to_vec())
+    }
+
+    /// Built in copy of `media/biomes.png`.
+    pub fn biomes() -> ColourRamp {
+        ColourRamp::new(BIOMES.to_vec())
+    }
+
+    /// Reads the top row of a PNG image, left to right.
+    pub fn from_png<P: AsRef<Path>>(path: P) -> io::Result<ColourRamp> {
+        let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        let (info, mut reader) = decoder.read_info()?;
+        let mut buf = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buf)?;
+
+        let samples = info.color_type.samples();
+        let colours = buf[..info.width as usize * samples]
+            .chunks(samples)
+            .map(|p| match info.color_type {
+                ColorType::Grayscale | ColorType::GrayscaleAlpha => [p[0], p[0], p[0]],
+                _ => [p[0], p[1], p[2]],
+            })
+            .collect::<Vec<_>>();
+        if colours.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty colour ramp"));
+        }
+        Ok(ColourRamp::new(colours))
+    }
+
+    pub fn len(&self) -> usize {
+        self.colours.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colours.is_empty()
+    }
+
+    /// Index of the colour at `t`, clamped to the ends of the ramp.
+    pub fn index(&self, t: f32) -> usize {
+        let t = t.clamp(0.0, 1.0);
+        ((t * self.colours.len() as f32) as usize).min(self.colours.len() - 1)
+    }
+
+    pub fn colour(&self, index: usize) -> [u8; 3] {
+        self.colours[index]
+    }
+
+    pub fn sample(&self, t: f32) -> [u8; 3] {
+        self.colour(self.index(t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sea_level_is_the_first_ocean_colour() {
+        let ramp = ColourRamp::elevation();
+
+        assert_eq!(ramp.sample(0.0), [108, 59, 23]);
+        assert_eq!(ramp.sample(0.49), [206, 152, 109]);
+        assert_eq!(ramp.sample(0.5), [58, 214, 255]);
+        assert_eq!(ramp.sample(1.0), [0, 87, 220]);
+        assert_eq!(ramp.sample(-3.0), ramp.sample(0.0));
+        assert_eq!(ramp.sample(3.0), ramp.sample(1.0));
+    }
+
+    #[test]
+    fn reads_top_row_of_media_ramp() {
+        let ramp = ColourRamp::from_png("media/elevation.png").unwrap();
+
+        assert_eq!(ramp.len(), 256);
+        for i in 0..32 {
+            assert_eq!(ramp.colour(i * 8), ColourRamp::elevation().colour(i));
+        }
+    }
+
+    #[test]
+    fn reads_top_row_of_temperature_ramp() {
+        let ramp = ColourRamp::from_png("media/temperature.png").unwrap();
+
+        assert_eq!(ramp.len(), 256);
+        for i in 0..32 {
+            assert_eq!(ramp.colour(i * 8), ColourRamp::temperature().colour(i));
+        }
+    }
+
+    #[test]
+    fn reads_middle_of_biome_groups() {
+        let ramp = ColourRamp::from_png("media/biomes.png").unwrap();
+
+        assert_eq!(ramp.len(), 256);
+        for i in 0..16 {
+            assert_eq!(ramp.colour(i * 16 + 8), ColourRamp::biomes().colour(i));
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use export::ramp::ColourRamp;
+use mesh::elevation_colour;
+use terrain::planet::Planet;
+use terrain::types::Vertex;
+
+use png::{self, BitDepth, ColorType, HasParameters};
+
+use std::f32;
+use std::f32::consts::PI;
+use std::io::{self, Write};
+
+/// Tile elevations sampled on an equirectangular grid, row by row from the north pole
+/// (+y) down, and west to east starting at longitude -180 degrees (-x).
+#[derive(Clone, Debug)]
+pub struct Heightmap {
+    pub width: u32,
+    pub height: u32,
+    pub elevations: Vec<f32>,
+    sea_level: f32,
+    lower_scale: f32,
+    upper_scale: f32,
+}
+
+impl Heightmap {
+    pub fn equirectangular(planet: &Planet, width: u32, height: u32) -> Heightmap {
+        let mut elevations = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let lat = PI / 2.0 - (y as f32 + 0.5) / height as f32 * PI;
+            for x in 0..width {
+                let lon = -PI + (x as f32 + 0.5) / width as f32 * 2.0 * PI;
+                let tile = planet.tile(planet.tile_at(&direction(lat, lon)));
+                elevations.push(planet.tile_elevation(tile));
+            }
+        }
+
+        let (lower_scale, upper_scale) = planet.get_elevation_scale();
+        Heightmap {
+            width,
+            height,
+            elevations,
+            sea_level: planet.sea_level(),
+            lower_scale,
+            upper_scale,
+        }
+    }
+
+    /// Elevations scaled linearly from the deepest point at 0 to the highest at 65535.
+    pub fn to_u16(&self) -> Vec<u16> {
+        self.elevations
+            .iter()
+            .map(|e| elevation_to_u16(e - self.sea_level, self.lower_scale, self.upper_scale))
+            .collect()
+    }
+
+    /// Writes a binary 16-bit greyscale PGM.
+    pub fn write_pgm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P5\n{} {}\n65535\n", self.width, self.height)?;
+        out.write_all(&self.to_big_endian())
+    }
+
+    /// Writes a 16-bit greyscale PNG.
+    pub fn write_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        encode_png(out,
+                   self.width,
+                   self.height,
+                   ColorType::Grayscale,
+                   BitDepth::Sixteen,
+                   &self.to_big_endian())
+    }
+
+    /// Writes an 8-bit RGB PNG coloured like the viewer, with `ramp` sampled as the
+    /// elevation texture.
+    pub fn write_colour_png<W: Write>(&self, ramp: &ColourRamp, out: &mut W) -> io::Result<()> {
+        let mut data = Vec::with_capacity(self.elevations.len() * 3);
+        for e in self.elevations.iter() {
+            let e = e - self.sea_level;
+            let c = ramp.sample(elevation_colour(e, self.lower_scale, self.upper_scale));
+            data.extend_from_slice(&c);
+        }
+        encode_png(out, self.width, self.height, ColorType::RGB, BitDepth::Eight, &data)
+    }
+
+    fn to_big_endian(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.elevations.len() * 2);
+        for h in self.to_u16() {
+            data.push((h >> 8) as u8);
+            data.push(h as u8);
+        }
+        data
+    }
+}
+
+/// Maps `[-lower_scale, upper_scale]` around sea level, as given by
+/// `Planet::get_elevation_scale`, linearly onto the full 16-bit range.
+pub fn elevation_to_u16(elevation: f32, lower_scale: f32, upper_scale: f32) -> u16 {
+    let range = lower_scale + upper_scale;
+    let h = if range > 0.0 {
+        (elevation + lower_scale) / range
+    } else {
+        0.5
+    };
+    (h.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+pub fn encode_png<W: Write>(out: &mut W,
+                            width: u32,
+                            height: u32,
+                            colour_type: ColorType,
+                            bit_depth: BitDepth,
+                            data: &[u8])
+                            -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set(colour_type).set(bit_depth);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    Ok(())
+}
+
+fn direction(lat: f32, lon: f32) -> Vertex {
+    Vertex::new(lat.cos() * lon.cos(), lat.sin(), lat.cos() * lon.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::DotProduct;
+    use terrain::generator::Generator;
+
+    fn planet() -> Planet {
+        let mut gen = Generator::new(21);
+        gen.subdivide();
+        gen.subdivide();
+        gen.to_planet()
+    }
+
+    fn heightmap() -> Heightmap {
+        Heightmap::equirectangular(&planet(), 64, 32)
+    }
+
+    #[test]
+    fn pixels_show_nearest_tile() {
+        let planet = planet();
+        let map = Heightmap::equirectangular(&planet, 64, 32);
+
+        assert_eq!(map.elevations.len(), 64 * 32);
+        for &(x, y) in [(0, 0), (17, 5), (32, 16), (63, 31), (40, 20)].iter() {
+            let lat = PI / 2.0 - (y as f32 + 0.5) / 32.0 * PI;
+            let lon = -PI + (x as f32 + 0.5) / 64.0 * 2.0 * PI;
+            let dir = direction(lat, lon);
+            let nearest = planet.tiles_iter()
+                .max_by(|a, b| {
+                    let da = planet.tile_normal(a).dot(&dir);
+                    let db = planet.tile_normal(b).dot(&dir);
+                    da.partial_cmp(&db).unwrap()
+                })
+                .unwrap();
+
+            assert_eq!(map.elevations[y * 64 + x], planet.tile_elevation(nearest));
+        }
+    }
+
+    #[test]
+    fn pgm_has_header_and_two_bytes_per_pixel() {
+        let map = heightmap();
+
+        let mut out = Vec::new();
+        map.write_pgm(&mut out).unwrap();
+
+        let header = b"P5\n64 32\n65535\n";
+        assert_eq!(&out[..header.len()], &header[..]);
+        assert_eq!(out.len(), header.len() + 64 * 32 * 2);
+    }
+
+    #[test]
+    fn png_decodes_to_same_size() {
+        let map = heightmap();
+
+        let mut out = Vec::new();
+        map.write_colour_png(&ColourRamp::elevation(), &mut out).unwrap();
+
+        let (info, _) = png::Decoder::new(&out[..]).read_info().unwrap();
+        assert_eq!((info.width, info.height), (64, 32));
+        assert_eq!(info.color_type, ColorType::RGB);
+    }
+}
@@ -0,0 +1,54 @@
+use terrain::planet::Planet;
+
+use std::io::{self, Write};
+
+/// Writes one line per tile with its position, elevation, plate, movement, climate and
+/// biome, left empty where no rule matches.
+pub fn write_tiles_csv<W: Write>(planet: &Planet, out: &mut W) -> io::Result<()> {
+    writeln!(out,
+             "tile,x,y,z,elevation,plate,movement_x,movement_y,movement_z,temperature,\
+              precipitation,biome")?;
+    for (idx, tile) in planet.tiles_iter().enumerate() {
+        let normal = planet.tile_normal(tile);
+        let movement = &tile.movement_vector;
+        writeln!(out,
+                 "{},{},{},{},{},{},{},{},{},{},{},{}",
+                 idx,
+                 normal.x,
+                 normal.y,
+                 normal.z,
+                 planet.tile_elevation(tile),
+                 tile.plate_id,
+                 movement.x,
+                 movement.y,
+                 movement.z,
+                 planet.tile_temperature(tile),
+                 planet.tile_precipitation(tile),
+                 planet.tile_biome(tile).map(|b| b.name()).unwrap_or(""))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::generator::Generator;
+
+    #[test]
+    fn writes_header_and_one_line_per_tile() {
+        let mut gen = Generator::new(5);
+        gen.subdivide();
+        let mut planet = gen.to_planet();
+        planet.update_surface();
+
+        let mut out = Vec::new();
+        write_tiles_csv(&planet, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), planet.num_tiles() + 1);
+        assert!(lines[0].starts_with("tile,"));
+        assert!(lines[1].starts_with("0,"));
+        assert_eq!(lines[1].split(',').count(), 12);
+    }
+}
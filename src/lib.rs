@@ -0,0 +1,16 @@
+extern crate rand;
+#[macro_use]
+extern crate log;
+extern crate noise;
+extern crate byteorder;
+extern crate png;
+
+pub mod math;
+pub mod stopwatch;
+pub mod terrain;
+pub mod mesh;
+pub mod export;
+
+pub use terrain::generator::Generator;
+pub use terrain::planet::Planet;
+pub use mesh::Mesh;
@@ -1,277 +1,279 @@
+extern crate terragen;
+#[cfg(feature = "viewer")]
 extern crate kiss3d;
+#[cfg(feature = "viewer")]
 extern crate glfw;
+#[cfg(feature = "viewer")]
 extern crate nalgebra as na;
+#[cfg(feature = "viewer")]
+extern crate gl;
 extern crate rand;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
-extern crate stopwatch;
-extern crate gl;
-extern crate noise;
 #[macro_use]
 extern crate clap;
 
-mod terrain;
-mod math;
-mod geom;
-mod render;
+mod batch;
+#[cfg(feature = "viewer")]
+mod viewer;
 
-use clap::App;
-use na::{Vector3, UnitQuaternion, Point2, Point3};
-use kiss3d::window::Window;
-use kiss3d::light::Light;
-use kiss3d::camera::ArcBall;
-use kiss3d::scene::SceneNode;
-use kiss3d::resource::{Mesh, Material};
-use kiss3d::text::Font;
+use clap::{App, ArgMatches, SubCommand};
+use env_logger::LogBuilder;
+use log::LogLevelFilter;
 
-use glfw::{Action, Key, WindowEvent};
+use terragen::{Generator, Planet};
+use terragen::terrain::planet::{BiomeRules, ClimateConfig, ErosionConfig, PlateConfig, Seeding};
 
-use terrain::generator::Generator;
-use terrain::planet::Planet;
-use geom::*;
-use render::WireframeMaterial;
+use std::env;
+use std::process;
+use std::str::FromStr;
 
-use std::rc::Rc;
-use std::cell::RefCell;
-use std::path::Path;
-use std::sync::mpsc::channel;
-use std::f32;
+const PIPELINE_ARGS: &str =
+    "-l, --level=[LEVEL] 'Sets subdivision level'
+    -d, --distortion=[RATE] 'Sets topology distortion rate [0.0 .. 1.0]'
+    -s, --seed=[SEED] 'Sets random seed (random if omitted)'
+    --resume=[FILE] 'Continues from a saved generator instead of starting over'
+    --checkpoint=[FILE] 'Saves the generator once the topology is done'
+    --ocean-ratio=[RATIO] 'Sets the share of oceanic plates [0.0 .. 1.0]'
+    --plates=[COUNT] 'Sets the number of plates to grow (27)'
+    --min-plate-size=[SHARE] 'Merges plates with a smaller share of tiles (0.033)'
+    --growth-bias=[BIAS] 'Grows rounder, more even plates for higher values (2.0)'
+    --seeding=[STRATEGY] 'Seeds plates at random or spread by poisson disc'
+    --axial-tilt=[DEGREES] 'Sets the tilt of the rotation axis that spreads the sunlight (23.44)'
+    --biome-rules=[FILE] 'Classifies biomes with the rules in a file like media/biomes.txt'
+    --sea-level=[METRES] 'Sets the elevation of the sea surface (0)'
+    --land-fraction=[SHARE] 'Sets the sea level that leaves this share of tiles dry [0.0 .. 1.0]'";
 
 fn main() {
-    env_logger::init().unwrap();
+    init_logging();
 
     let matches = App::new("terragen")
         .version(crate_version!())
-        .args_from_usage(
-            "-l, --level=[LEVEL] 'Sets subdivision level'
-            -d, --distortion=[RATE] 'Sets topology distortion rate [0.0 .. 1.0]'")
+        .args_from_usage(PIPELINE_ARGS)
+        .args_from_usage("-i, --input=[FILE] 'Views a saved planet instead of generating one'")
+        .subcommand(SubCommand::with_name("generate")
+            .about("Generates a planet and writes it to disk without opening a window")
+            .args_from_usage(PIPELINE_ARGS)
+            .args_from_usage("-o, --output=<FILE> 'Sets the output file, format chosen by \
+                              extension (csv, planet, obj, glb, pgm, png)'
+                              --steps=[N] 'Moves the plates for N time steps before writing'
+                              --erosion=[N] 'Erodes the terrain for N iterations before writing'
+                              --talus-slope=[M_PER_KM] 'Sets the steepest slope that does not \
+                              slump (8)'
+                              --erodibility=[K] 'Sets how fast rivers cut into the ground (0.0002)'
+                              --deposition=[RATE] 'Sets the share of excess sediment that \
+                              settles (0.5)'
+                              --obj-groups=[GROUPING] 'Groups OBJ faces per tile or plate'
+                              --glb-colours=[COLOURING] 'Colours glTF by elevation, plate, \
+                              temperature or biome'
+                              --map-size=[SIZE] 'Sets heightmap size as WIDTHxHEIGHT (2048x1024)'
+                              --colour-map=[FILE] 'Also writes a colourised heightmap PNG'
+                              --cube-size=[PIXELS] 'Writes PNG output as six cube map faces'
+                              --cube-channel=[CHANNEL] 'Writes elevation, colour, plate or biome \
+                              to cube faces'
+                              --cube-atlas 'Writes all cube faces side by side to the output file'
+                              --ramp=[FILE] 'Colours exported tiles from the top row of a PNG'"))
         .get_matches();
 
-    // 0 -- 0.15
-    let topology_distortion_rate = matches.value_of("distortion").unwrap_or("0.25").parse::<f32>().unwrap() * 0.15;
-    let subdivision_level = matches.value_of("level").unwrap_or("4").parse::<u32>().unwrap();
-
-    println!("Topology distortion rate: {}", topology_distortion_rate);
-    println!("Subdivision level: {}", subdivision_level);
+    let result = match matches.subcommand() {
+        ("generate", Some(sub_matches)) => batch::run(sub_matches),
+        _ => view(&matches),
+    };
 
-    let (tx, rx) = channel();
-
-    let mut terr = Generator::new();
-    for _ in 0..subdivision_level {
-        terr.subdivide();
+    if let Err(e) = result {
+        error!("{}", e);
+        process::exit(1);
     }
-    terr.introduce_chaos(topology_distortion_rate);
-
-    let mut generator: Option<Generator> = Some(terr);
-    let mut planet: Option<Planet> = None;
-
-    let mut window = Window::new_with_size("Terragen", 900, 900);
+}
 
-    let font = Font::new(&Path::new("media/1942_report/1942.ttf"), 50);
+#[cfg(feature = "viewer")]
+fn view(matches: &ArgMatches) -> Result<(), String> {
+    match matches.value_of("input") {
+        Some(input) => {
+            let mut planet = Planet::load_from_file(input)
+                .map_err(|e| format!("Failed to load {}: {}", input, e))?;
+            info!("Loaded {} tiles from {}", planet.num_tiles(), input);
+            let sea_level = parse_sea_level(matches, &planet)?;
+            let climate = parse_climate_config(matches, *planet.climate_config())?;
+            planet.set_surface(sea_level, climate, parse_biome_rules(matches)?);
+            viewer::run(None, Some(planet));
+        }
+        None => {
+            let generator = build_generator(matches)?;
+            let mut planet = generator.to_planet_with(parse_plate_config(matches)?);
+            let sea_level = parse_sea_level(matches, &planet)?;
+            let climate = parse_climate_config(matches, ClimateConfig::default())?;
+            planet.set_surface(sea_level, climate, parse_biome_rules(matches)?);
+            viewer::run(Some(generator), Some(planet));
+        }
+    }
+    Ok(())
+}
 
-    let eye = Point3::new(0.0, 2.0, 50.0);
-    let at = Point3::origin();
-    let mut arc_ball = ArcBall::new(eye, at);
+#[cfg(not(feature = "viewer"))]
+fn view(_matches: &ArgMatches) -> Result<(), String> {
+    Err("Built without the viewer feature, use the generate subcommand".to_string())
+}
 
-    window.set_light(Light::StickToCamera);
+fn init_logging() {
+    let mut builder = LogBuilder::new();
+    builder.filter(None, LogLevelFilter::Info);
+    if let Ok(spec) = env::var("RUST_LOG") {
+        builder.parse(&spec);
+    }
+    builder.init().unwrap();
+}
 
-    let wireframe_material = Rc::new(RefCell::new(Box::new(WireframeMaterial::new()) as
-        Box<Material + 'static>));
+pub fn parse_arg<T: FromStr>(matches: &ArgMatches, name: &str, default: T) -> Result<T, String> {
+    match matches.value_of(name) {
+        Some(value) => {
+            value.parse::<T>().map_err(|_| format!("Invalid value for --{}: {}", name, value))
+        }
+        None => Ok(default),
+    }
+}
 
-    let rot = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.001);
+pub fn parse_plate_config(matches: &ArgMatches) -> Result<PlateConfig, String> {
+    let defaults = PlateConfig::default();
+    let config = PlateConfig {
+        num_plates: parse_arg(matches, "plates", defaults.num_plates)?,
+        min_plate_size: parse_arg(matches, "min-plate-size", defaults.min_plate_size)?,
+        growth_bias: parse_arg(matches, "growth-bias", defaults.growth_bias)?,
+        seeding: match matches.value_of("seeding") {
+            None | Some("random") => Seeding::Random,
+            Some("poisson") => Seeding::PoissonDisc,
+            Some(other) => return Err(format!("Invalid value for --seeding: {}", other)),
+        },
+        ocean_ratio: parse_arg(matches, "ocean-ratio", defaults.ocean_ratio)?,
+    };
+    if config.num_plates == 0 {
+        return Err("Plate count must be at least 1".to_string());
+    }
+    if config.min_plate_size < 0.0 || config.min_plate_size > 1.0 {
+        return Err(format!("Minimum plate size must be between 0 and 1, was {}",
+                           config.min_plate_size));
+    }
+    if config.growth_bias <= 0.0 {
+        return Err(format!("Growth bias must be positive, was {}", config.growth_bias));
+    }
+    if config.ocean_ratio < 0.0 || config.ocean_ratio > 1.0 {
+        return Err(format!("Ocean ratio must be between 0 and 1, was {}", config.ocean_ratio));
+    }
+    Ok(config)
+}
 
-    let mut grp = window.add_group();
-    let mut planet_node: Option<SceneNode> = None;
+/// Climate settings from the command line, with `defaults` for those not given.
+pub fn parse_climate_config(matches: &ArgMatches,
+                            defaults: ClimateConfig)
+                            -> Result<ClimateConfig, String> {
+    let config = ClimateConfig {
+        axial_tilt: parse_arg(matches, "axial-tilt", defaults.axial_tilt)?,
+        ..defaults
+    };
+    if config.axial_tilt < 0.0 || config.axial_tilt > 180.0 {
+        return Err(format!("Axial tilt must be between 0 and 180 degrees, was {}",
+                           config.axial_tilt));
+    }
+    Ok(config)
+}
 
-    let visualization_types = [Visualization::Dual, Visualization::Plates];
-    let mut visualization_index = 0;
-    let mut regenerate_mesh = true;
-    let mut use_wireframe = true;
-    let mut rotate = false;
-    let mut current_level = 0;
-    let mut num_tiles = 0;
-    let mut max_depth = 0.0;
-    let mut max_elevation = 0.0;
+pub fn parse_biome_rules(matches: &ArgMatches) -> Result<BiomeRules, String> {
+    match matches.value_of("biome-rules") {
+        Some(path) => {
+            BiomeRules::from_file(path)
+                .map_err(|e| format!("Failed to read biome rules {}: {}", path, e))
+        }
+        None => Ok(BiomeRules::default()),
+    }
+}
 
-    while window.render_with_camera(&mut arc_ball) {
-        if let Some(ref gen) = generator {
-            current_level = gen.current_level();
+/// Sea level from `--sea-level`, or where it leaves `--land-fraction` of the planet dry.
+/// The planet's current sea level when neither is given.
+pub fn parse_sea_level(matches: &ArgMatches, planet: &Planet) -> Result<f32, String> {
+    match (matches.value_of("sea-level"), matches.value_of("land-fraction")) {
+        (Some(_), Some(_)) => {
+            Err("Use either --sea-level or --land-fraction, not both".to_string())
         }
-        if let Some(ref pla) = planet {
-            num_tiles = pla.num_tiles();
-            let (d, e) = pla.get_elevation_scale();
-            max_depth = d;
-            max_elevation = e;
+        (Some(_), None) => parse_arg(matches, "sea-level", 0.0f32),
+        (None, Some(_)) => {
+            let fraction = parse_arg(matches, "land-fraction", 0.0f32)?;
+            if !(0.0..=1.0).contains(&fraction) {
+                return Err(format!("Land fraction must be between 0 and 1, was {}", fraction));
+            }
+            Ok(planet.sea_level_for_land_fraction(fraction))
         }
-        window.draw_text(&format!("Level: {}\nTiles: {}", current_level, num_tiles),
-                         &Point2::new(50.0, 50.0),
-                         &font,
-                         &Point3::new(1.0, 1.0, 1.0));
+        (None, None) => Ok(planet.sea_level()),
+    }
+}
 
-        window.draw_text(&format!("Depth: {:.0}\nElevation: {:.0}", max_depth, max_elevation),
-                         &Point2::new(50.0, 1600.0),
-                         &font,
-                         &Point3::new(1.0, 1.0, 1.0));
+pub fn parse_erosion_config(matches: &ArgMatches) -> Result<ErosionConfig, String> {
+    let defaults = ErosionConfig::default();
+    let config = ErosionConfig {
+        iterations: parse_arg(matches, "erosion", defaults.iterations)?,
+        talus_slope: parse_arg(matches, "talus-slope", defaults.talus_slope)?,
+        erodibility: parse_arg(matches, "erodibility", defaults.erodibility)?,
+        deposition: parse_arg(matches, "deposition", defaults.deposition)?,
+        ..defaults
+    };
+    if config.talus_slope < 0.0 {
+        return Err(format!("Talus slope must not be negative, was {}", config.talus_slope));
+    }
+    if config.erodibility < 0.0 {
+        return Err(format!("Erodibility must not be negative, was {}", config.erodibility));
+    }
+    if config.deposition < 0.0 || config.deposition > 1.0 {
+        return Err(format!("Deposition must be between 0 and 1, was {}", config.deposition));
+    }
+    Ok(config)
+}
 
-        for mut event in window.events().iter() {
-            match event.value {
-                /*
-                WindowEvent::Key(Key::Space, _, Action::Release, _) => {
-                    if let Some(ref mut gen) = generator {
-                        info!("Subdividing a level {} terrain", gen.current_level());
-                        let sw = Stopwatch::start_new();
-                        gen.subdivide();
-                        planet = None;
-                        info!("Subdivision took {} ms", sw.elapsed_ms());
-                        // (1744 ms, lvl 6), (7401 ms, lvl 7)
-                        regenerate_mesh = true;
-                        event.inhibited = true
-                    }
-                }
-                WindowEvent::Key(Key::A, _, Action::Release, _) => {
-                    if let Some(ref mut gen) = generator {
-                        let mut total_distortion =
-                            (gen.num_edges() as f32 * topology_distortion_rate).ceil() as u32;
-                        let mut iterations = 6;
-                        while iterations > 0 {
-                            let iteration_distortion = total_distortion / iterations;
-                            total_distortion -= iteration_distortion;
-                            gen.distort(iteration_distortion);
-                            gen.relax(0.);
-                            iterations -= 1;
-                        }
-                        planet = None;
-                        regenerate_mesh = true;
-                        event.inhibited = true;
-                    }
-                }
-                WindowEvent::Key(Key::S, _, Action::Release, _) => {
-                    if let Some(ref mut gen) = generator {
-                        let max_relax = 300;
-                        let mut last_move = f32::MAX;
-                        let mut i = 1;
-                        let num_nodes = gen.num_nodes() as f32;
-                        let average_node_radius = (f32::consts::PI * 4.0 / num_nodes).sqrt();
-                        let min_shift_delta = average_node_radius * num_nodes / 500000.0;
-                        while i <= max_relax {
-                            let rel = gen.relax(0.5);
-                            debug!("Relaxation iteration {}: {}", i, rel);
-                            let diff = (last_move - rel).abs();
-                            if diff < min_shift_delta {
-                                debug!("Relaxation converging with diff {}", diff);
-                                break;
-                            }
-                            last_move = rel;
-                            i += 1;
-                        }
-                        planet = None;
-                        regenerate_mesh = true;
-                        event.inhibited = true
-                    }
-                }
-                */
-                WindowEvent::Key(Key::D, _, Action::Release, _) => {
-                    visualization_index = (visualization_index + 1) % visualization_types.len();
-                    regenerate_mesh = true;
-                    event.inhibited = true
-                }
-                WindowEvent::Key(Key::R, _, Action::Release, _) => {
-                    rotate = !rotate;
-                    event.inhibited = true
-                }
-                WindowEvent::Key(Key::W, _, Action::Release, _) => {
-                    use_wireframe = !use_wireframe;
-                    regenerate_mesh = true;
-                    event.inhibited = true;
-                }
-                WindowEvent::Key(Key::G, _, Action::Release, _) => {
-                    if let Some(ref mut pla) = planet {
-                        pla.grow_plates();
-                        regenerate_mesh = true;
-                        event.inhibited = true;
-                    }
-                }
-                WindowEvent::Key(Key::M, _, Action::Release, _) => {
-                    if let Some(ref mut pla) = planet {
-                        pla.merge_plates();
-                        regenerate_mesh = true;
-                        event.inhibited = true;
-                    }
-                }
-                _ => {}
-            }
-        }
-        if regenerate_mesh {
-            if let Some(gen) = generator {
-                let p = planet.unwrap_or_else(|| gen.to_planet());
-                generate(visualization_types[visualization_index],
-                         gen,
-                         p,
-                         use_wireframe,
-                         &tx);
-                generator = None;
-                planet = None;
+pub fn build_generator(matches: &ArgMatches) -> Result<Generator, String> {
+    // 0 -- 0.15
+    let topology_distortion_rate = parse_arg(matches, "distortion", 0.25f32)? * 0.15;
+
+    info!("Topology distortion rate: {}", topology_distortion_rate);
+
+    let terr = match matches.value_of("resume") {
+        Some(resume) => {
+            let mut terr = Generator::load_from_file(resume)
+                .map_err(|e| format!("Failed to resume from {}: {}", resume, e))?;
+            if matches.is_present("seed") {
+                warn!("Ignoring --seed, continuing with seed {} from {}", terr.seed(), resume);
             }
-            regenerate_mesh = false;
-        }
-        match rx.try_recv() {
-            Ok(Message::Complete(vertices, faces, normals, texcoords, terr, pla)) => {
-                if let Some(mut c) = planet_node {
-                    window.remove(&mut c);
+            let subdivision_level = parse_arg(matches, "level", terr.current_level() as u32)?;
+            info!("Resuming level {} generator from {}", terr.current_level(), resume);
+            info!("Subdivision level: {}", subdivision_level);
+
+            if (terr.current_level() as u32) < subdivision_level {
+                while (terr.current_level() as u32) < subdivision_level {
+                    terr.subdivide();
                 }
-                let (wirecoords, wirefaces) = generate_plate_vectors(&pla);
-                planet_node = Some(add_mesh(visualization_types[visualization_index],
-                                            &mut grp,
-                                            vertices,
-                                            faces,
-                                            normals,
-                                            texcoords,
-                                            Some((wirecoords, wirefaces)),
-                                            wireframe_material.clone()));
-                generator = Some(terr);
-                planet = Some(pla);
+                terr.introduce_chaos(topology_distortion_rate);
             }
-            _ => {}
+            terr
         }
-        if rotate {
-            grp.prepend_to_local_rotation(&rot);
-        }
-    }
-}
+        None => {
+            let subdivision_level = parse_arg(matches, "level", 4u32)?;
+            let seed = parse_arg(matches, "seed", rand::random::<u64>())?;
 
-fn add_mesh(visualization: Visualization,
-            parent: &mut SceneNode,
-            vertices: Vec<Point3<f32>>,
-            faces: Vec<Point3<u32>>,
-            normals: Option<Vec<Vector3<f32>>>,
-            texcoords: Option<Vec<Point2<f32>>>,
-            wireframes: Option<(Vec<Point3<f32>>, Vec<Point3<u32>>)>,
-            wireframe_material: Rc<RefCell<Box<Material + 'static>>>)
-            -> SceneNode {
-    let mut grp = parent.add_group();
-    if let Some((line_verts, line_faces)) = wireframes {
-        let mesh = Mesh::new(line_verts, line_faces, None, None, false);
-        let mesh = Rc::new(RefCell::new(mesh));
-        let scale = 1.001;
-        let mut c = grp.add_mesh(mesh, Vector3::new(scale, scale, scale));
+            info!("Subdivision level: {}", subdivision_level);
+            info!("Seed: {}", seed);
 
-        c.set_color(1.0, 1.0, 1.0);
-        c.set_lines_width(2.0);
-        c.set_material(wireframe_material);
-    }
-    let mesh = Mesh::new(vertices, faces, normals, texcoords, false);
-    let mesh = Rc::new(RefCell::new(mesh));
-
-    let mut c = grp.add_mesh(mesh.clone(), Vector3::new(1.0, 1.0, 1.0));
+            let mut terr = Generator::new(seed);
+            for _ in 0..subdivision_level {
+                terr.subdivide();
+            }
+            terr.introduce_chaos(topology_distortion_rate);
+            terr
+        }
+    };
 
-    c.set_color(1.0, 1.0, 1.0);
-    if visualization == Visualization::Plates {
-        c.set_texture_from_file(&Path::new("media/groups.png"), "groups");
-    } else {
-        c.set_texture_from_file(&Path::new("media/elevation.png"), "elevation");
+    if let Some(checkpoint) = matches.value_of("checkpoint") {
+        terr.save_to_file(checkpoint)
+            .map_err(|e| format!("Failed to write checkpoint {}: {}", checkpoint, e))?;
+        info!("Saved level {} generator to {}", terr.current_level(), checkpoint);
     }
-    c.enable_backface_culling(true);
 
-    grp
+    Ok(terr)
 }
@@ -20,6 +20,13 @@ pub fn lerp(v0: &Vec3<f32>, v1: &Vec3<f32>, t: f32) -> Vec3<f32> {
     v0 * (1.0 - t) + v1 * t
 }
 
+/// Rotates `v` by `angle` radians around the unit vector `axis`, counter clockwise when
+/// looking down the axis, using Rodrigues' rotation formula.
+pub fn rotate(v: &Vec3<f32>, axis: &Vec3<f32>, angle: f32) -> Vec3<f32> {
+    let (sin, cos) = angle.sin_cos();
+    v * cos + axis.cross(v) * sin + axis * (axis.dot(v) * (1.0 - cos))
+}
+
 pub fn distance(v0: &Vec3<f32>, v1: &Vec3<f32>) -> f32 {
     (v1 - v0).length()
 }
@@ -27,6 +34,7 @@ pub fn distance(v0: &Vec3<f32>, v1: &Vec3<f32>) -> f32 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::f32;
 
     #[test]
     fn normalize_vector() {
@@ -55,4 +63,17 @@ mod tests {
                    "slerp(t = 0.5) should be between points");
 
     }
+
+    #[test]
+    fn rotate_vector() {
+        let a = Vec3::new(1.0, 0.0, 0.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        let b = rotate(&a, &up, f32::consts::PI / 2.0);
+        assert!(distance(&b, &Vec3::new(0.0, 0.0, -1.0)) < 1e-6);
+
+        let back = rotate(&b, &up, -f32::consts::PI / 2.0);
+        assert!(distance(&back, &a) < 1e-6);
+        assert!(distance(&rotate(&up, &up, 1.0), &up) < 1e-6);
+    }
 }
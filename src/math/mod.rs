@@ -1,10 +1,12 @@
 mod vector;
 mod linalg;
 mod stat;
+mod random;
 
 pub use math::vector::{Vec3, DotProduct};
-pub use math::linalg::{normalize, lerp, slerp, distance};
+pub use math::linalg::{normalize, lerp, slerp, rotate, distance};
 pub use math::stat::{variance, into_variance};
+pub use math::random::TerrainRng;
 
 pub fn sorted_pair<T>(a: T, b: T) -> (T, T)
     where T: Ord
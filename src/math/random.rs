@@ -0,0 +1,91 @@
+use rand::Rng;
+
+/// Xorshift128 random number generator seeded from a single `u64`.
+///
+/// All random decisions during generation go through this type, so that the same seed
+/// always yields the same planet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerrainRng {
+    state: [u32; 4],
+}
+
+impl TerrainRng {
+    pub fn new(seed: u64) -> TerrainRng {
+        // Expand the seed with splitmix64, which never yields an all-zero state
+        let mut s = seed;
+        let mut next = || {
+            s = s.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = s;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^ (z >> 31)
+        };
+        let a = next();
+        let b = next();
+        TerrainRng { state: [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32] }
+    }
+
+    /// Restores a generator from a previously saved `state()`. Returns `None` for the
+    /// all-zero state, from which xorshift never recovers.
+    pub fn from_state(state: [u32; 4]) -> Option<TerrainRng> {
+        if state == [0; 4] {
+            None
+        } else {
+            Some(TerrainRng { state })
+        }
+    }
+
+    pub fn state(&self) -> [u32; 4] {
+        self.state
+    }
+}
+
+impl Rng for TerrainRng {
+    fn next_u32(&mut self) -> u32 {
+        let x = self.state[0];
+        let t = x ^ (x << 11);
+        self.state[0] = self.state[1];
+        self.state[1] = self.state[2];
+        self.state[2] = self.state[3];
+        let w = self.state[3];
+        self.state[3] = w ^ (w >> 19) ^ (t ^ (t >> 8));
+        self.state[3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        let mut a = TerrainRng::new(42);
+        let mut b = TerrainRng::new(42);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+    }
+
+    #[test]
+    fn restored_state_continues_sequence() {
+        let mut a = TerrainRng::new(42);
+        a.next_u32();
+        let mut b = TerrainRng::from_state(a.state()).unwrap();
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_sequences() {
+        let mut a = TerrainRng::new(1);
+        let mut b = TerrainRng::new(2);
+
+        let sa: Vec<u32> = (0..10).map(|_| a.next_u32()).collect();
+        let sb: Vec<u32> = (0..10).map(|_| b.next_u32()).collect();
+
+        assert!(sa != sb);
+    }
+}
@@ -0,0 +1,455 @@
+use math::normalize;
+use terrain::generator::Generator;
+use terrain::planet::{Biome, Planet, Tile, BoundaryType};
+use terrain::types::{Vertex, Index3};
+use stopwatch::Stopwatch;
+
+use std::f32;
+
+/// Renderer agnostic triangle mesh, with one normal and texture coordinate per vertex.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub faces: Vec<Index3>,
+    pub normals: Vec<Vertex>,
+    pub texcoords: Vec<(f32, f32)>,
+}
+
+fn elevation_to_uv(elevation: f32, min_elev: f32, max_elev: f32) -> (f32, f32) {
+    let d = max_elev - min_elev;
+    let scaled_elev = if d.abs() > 0.01 {
+        (elevation - min_elev) / (max_elev - min_elev)
+    } else {
+        0.5
+    };
+    (1.0 - scaled_elev.powf(1.5), 0.0)
+}
+
+/// What the texture coordinates of a dual mesh look up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DualColouring {
+    /// The elevation ramp
+    Elevation,
+    /// The group palette, one colour per plate
+    Plates,
+    /// The group palette, by the strongest plate boundary along each tile
+    Boundaries,
+    /// The temperature ramp
+    Temperature,
+    /// The biome palette
+    Biomes,
+}
+
+/// Position along the elevation ramp, from the highest peak at 0.0 to the deepest trench
+/// at 1.0, with sea level in the middle. The elevation is relative to sea level, and the
+/// scales are as given by `get_elevation_scale`.
+pub fn elevation_colour(elevation: f32, lower_scale: f32, upper_scale: f32) -> f32 {
+    let mut elevation = elevation;
+    if elevation < 0.0 {
+        elevation /= lower_scale;
+    }
+    else if elevation >= 0.0 {
+        elevation /= upper_scale;
+    }
+
+    (1.0 - elevation) / 2.0
+}
+
+/// Position along the temperature ramp, from -40 °C at 0.0 to 40 °C at 1.0. The range is
+/// fixed so that colours mean the same on every planet.
+pub fn temperature_colour(temperature: f32) -> f32 {
+    (temperature + 40.0) / 80.0
+}
+
+/// Position of the tile's plate along the 16 colour group palette.
+pub fn plate_colour(tile: &Tile) -> f32 {
+    let pid = if tile.plate_id == 0 {
+        0
+    } else {
+        (tile.plate_id - 1) % 15 + 1
+    };
+
+    (pid as f32 + 0.5) / 16.0
+}
+
+/// Position along the group palette for the plate boundary of the tile that has the
+/// most relative movement: red for convergent, blue for divergent and yellow for
+/// transform boundaries. Tiles inside plates are white.
+pub fn boundary_colour(planet: &Planet, tile: &Tile) -> f32 {
+    let strongest = tile.borders
+        .iter()
+        .filter_map(|b| planet.border(*b).boundary)
+        .max_by(|a, b| {
+            let sa = a.pressure.abs().max(a.shear);
+            let sb = b.pressure.abs().max(b.shear);
+            sa.partial_cmp(&sb).unwrap()
+        });
+    let group = match strongest.map(|b| b.boundary_type) {
+        Some(BoundaryType::Convergent) => 5,
+        Some(BoundaryType::Divergent) => 2,
+        Some(BoundaryType::Transform) => 10,
+        None => 0,
+    };
+
+    (group as f32 + 0.5) / 16.0
+}
+
+/// Position of the biome along the 16 colour biome palette, where the first colour is
+/// for tiles that no rule matches.
+pub fn biome_colour(biome: Option<Biome>) -> f32 {
+    let group = biome.map(|b| b.index() + 1).unwrap_or(0);
+
+    (group as f32 + 0.5) / 16.0
+}
+
+pub fn generate_regular(generator: &Generator) -> Mesh {
+    let num_faces = generator.faces.len();
+    let num_vertices = num_faces * 3;
+    let (min_elev, max_elev) = generator.calculate_elevations();
+    let mut vertices = Vec::with_capacity(num_vertices);
+    let mut normals = Vec::with_capacity(num_vertices);
+    let mut texcoords = Vec::with_capacity(num_vertices);
+    let mut faces = Vec::with_capacity(num_faces);
+
+    {
+        let gen_faces = &generator.faces;
+        let gen_vertices = &generator.nodes;
+
+        let mut vert_index = 0u32;
+        for f in gen_faces.iter() {
+            let mut average_elevation = 0.0;
+            for idx in [f.points.x, f.points.y, f.points.z].iter() {
+                let vert = &gen_vertices[*idx as usize];
+                average_elevation += vert.elevation;
+                //let vertex_scale = (elevation.powi(2) - 0.5) * 0.02;
+                let vertex = &vert.point; // * (1.0 + vertex_scale);
+
+                vertices.push(vertex.clone());
+                let normal = normalize(generator.face_midpoint(f));
+                normals.push(normal);
+            }
+            let uv = elevation_to_uv(average_elevation / 3.0, min_elev, max_elev);
+            for _ in 0..3 {
+                texcoords.push(uv);
+            }
+
+            faces.push(Index3::new(vert_index, vert_index + 1, vert_index + 2));
+            vert_index += 3;
+        }
+    }
+
+    Mesh {
+        vertices,
+        faces,
+        normals,
+        texcoords,
+    }
+}
+/*
+
+  Generated vectors @ 0 ms
+  Calculated min/max elev @ 8 ms
+  Built node -> face index @ 214 ms
+  Built node -> edge index @ 441 ms
+  Built edge -> face index @ 702 ms
+  Built midpoint registry @ 1547 ms
+  Generated mesh in 4602 ms
+    Segment A: 187 ms
+    Segment B: 3796 ms
+      Segment B:0: 31 ms
+      Segment B:1: 416 ms
+      Segment B:2: 547 ms
+      Segment B:3: 1307 ms
+      Segment B:4: 212 ms
+    Segment C: 578 ms
+    Capacity mesh_faces:     983040 / 983052
+    Capacity mesh_vertices:  1146882 / 1146894
+    Capacity mesh_normals:   1146882 / 1146894
+    Capacity mesh_texcoords: 1146882 / 1146894
+  Creating mesh object in 0 ms
+
+ */
+
+pub fn generate_dual(planet: &Planet,
+                     generate_wireframe: bool,
+                     colouring: DualColouring)
+                     -> Mesh {
+    debug!("  Generator started...");
+    let mut sw = Stopwatch::start_new();
+
+    // Count how many triangles we will need
+
+    let mut num_faces = 0;
+
+    for tile in planet.tiles_iter() {
+        num_faces += tile.num_vertices();
+    }
+
+    let mut num_vertices = num_faces + planet.num_tiles();
+    if generate_wireframe {
+        num_vertices += num_faces;
+        num_faces += num_faces * 2;
+    }
+
+    let mut mesh_faces = Vec::with_capacity(num_faces);
+    let mut mesh_vertices = Vec::with_capacity(num_vertices);
+    let mut mesh_normals = Vec::with_capacity(mesh_vertices.capacity());
+    let mut mesh_texcoords = Vec::with_capacity(mesh_vertices.capacity());
+
+    debug!("    Capacity mesh_faces:     {} / {}",
+           mesh_faces.len(),
+           mesh_faces.capacity());
+    debug!("    Capacity mesh_vertices:  {} / {}",
+           mesh_vertices.len(),
+           mesh_vertices.capacity());
+    debug!("    Capacity mesh_normals:   {} / {}",
+           mesh_normals.len(),
+           mesh_normals.capacity());
+    debug!("    Capacity mesh_texcoords: {} / {}",
+           mesh_texcoords.len(),
+           mesh_texcoords.capacity());
+
+    debug!("  Generated vectors @ {} ms", sw.elapsed_ms());
+
+    let mut pentagons = 0;
+    let mut hexagons = 0;
+    let mut heptagons = 0;
+    let mut othergons = 0;
+
+    sw.restart();
+
+    let (lower_scale, upper_scale) = planet.get_elevation_scale();
+
+    let mut vertex_index = 0;
+    for tile in planet.tiles_iter() {
+
+        let normal = planet.tile_normal(tile);
+
+        let colour = match colouring {
+            DualColouring::Elevation => {
+                elevation_colour(planet.tile_elevation(tile) - planet.sea_level(),
+                                 lower_scale,
+                                 upper_scale)
+            }
+            DualColouring::Plates => plate_colour(tile),
+            DualColouring::Boundaries => boundary_colour(planet, tile),
+            DualColouring::Temperature => temperature_colour(planet.tile_temperature(tile)),
+            DualColouring::Biomes => biome_colour(planet.tile_biome(tile)),
+        };
+
+        let uv = (colour.clamp(0.0, 1.0), 0.10);
+        let uv_outer = if generate_wireframe {
+            (colour.clamp(0.0, 1.0), 0.4)
+        } else {
+            uv
+        };
+
+        // Center
+        mesh_vertices.push(planet.tile_midpoint(tile));
+        mesh_normals.push(normal.clone());
+        let center_uv = (colour.clamp(0.0, 1.0), 0.0);
+        mesh_texcoords.push(center_uv);
+
+        let mut n = 0;
+        for v in planet.tile_border_points(tile).into_iter() {
+            mesh_vertices.push(v);
+            mesh_normals.push(normal.clone());
+            mesh_texcoords.push(uv_outer);
+            n += 1;
+        }
+
+        if generate_wireframe {
+            let mp = planet.tile_midpoint(tile);
+            for v in planet.tile_border_points(tile).iter() {
+                let delta = (v - &mp) * 0.90 + &mp;
+                mesh_vertices.push(delta);
+                mesh_normals.push(normal.clone());
+                mesh_texcoords.push(uv);
+            }
+        }
+
+
+        let center = vertex_index;
+        for j in 0..n {
+            let p1 = vertex_index + 1 + j;
+            let p2 = vertex_index + 1 + (j + 1) % n;
+            if generate_wireframe {
+                let p1_inner = p1 + n;
+                let p2_inner = p2 + n;
+
+                mesh_faces.push(Index3::new(center, p1_inner, p2_inner));
+                mesh_faces.push(Index3::new(p1_inner, p1, p2_inner));
+                mesh_faces.push(Index3::new(p1, p2, p2_inner));
+            } else {
+                mesh_faces.push(Index3::new(center, p1, p2));
+            }
+        }
+
+        match n {
+            5 => pentagons += 1,
+            6 => hexagons += 1,
+            7 => heptagons += 1,
+            _ => othergons += 1,
+        }
+
+        vertex_index += n + 1;
+        if generate_wireframe {
+            vertex_index += n;
+        }
+    }
+
+    debug!("  Generated mesh in {} ms", sw.elapsed_ms()); // (2944 ms)
+
+    debug!("    Capacity mesh_faces:     {} / {}",
+           mesh_faces.len(),
+           mesh_faces.capacity());
+    debug!("    Capacity mesh_vertices:  {} / {}",
+           mesh_vertices.len(),
+           mesh_vertices.capacity());
+    debug!("    Capacity mesh_normals:   {} / {}",
+           mesh_normals.len(),
+           mesh_normals.capacity());
+    debug!("    Capacity mesh_texcoords: {} / {}",
+           mesh_texcoords.len(),
+           mesh_texcoords.capacity());
+
+    let total_faces = pentagons + hexagons + heptagons;
+    debug!("  Number of tiles: {}", total_faces);
+    debug!("    Pentagons: {}", pentagons);
+    debug!("    Hexagons : {}", hexagons);
+    debug!("    Heptagons: {}", heptagons);
+    if othergons > 0 {
+        debug!("  Also found {} tiles of other sizes", othergons);
+    }
+    debug!("  Earth analogy: average tile is {} km^2",
+           510100000 / total_faces);
+
+    Mesh {
+        vertices: mesh_vertices,
+        faces: mesh_faces,
+        normals: mesh_normals,
+        texcoords: mesh_texcoords,
+    }
+}
+
+/// Line segments from each tile midpoint along its movement vector, as pairs of indices.
+pub fn generate_plate_vectors(planet: &Planet) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let vector_scale = arrow_scale(planet);
+    for tile in planet.tiles_iter() {
+        let a = planet.tile_midpoint(tile);
+        let b = &a + &tile.movement_vector * vector_scale;
+        indices.push(vertices.len() as u32);
+        vertices.push(a);
+        indices.push(vertices.len() as u32);
+        vertices.push(b);
+    }
+
+    (vertices, indices)
+}
+
+/// Line segments from each ocean tile midpoint along its surface current, as pairs of
+/// indices.
+pub fn generate_currents(planet: &Planet) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let vector_scale = arrow_scale(planet);
+    for tile in planet.tiles_iter().filter(|t| planet.is_ocean(t)) {
+        let a = planet.tile_midpoint(tile);
+        let b = &a + planet.tile_current(tile) * vector_scale;
+        indices.push(vertices.len() as u32);
+        vertices.push(a);
+        indices.push(vertices.len() as u32);
+        vertices.push(b);
+    }
+
+    (vertices, indices)
+}
+
+/// Length of the longest arrows, about the width of two tiles.
+fn arrow_scale(planet: &Planet) -> f32 {
+    let radius = 10.0;
+    let total_surface_area = 4.0 * f32::consts::PI * radius * radius;
+    let tile_surface_area = total_surface_area / planet.num_tiles() as f32;
+    let approximate_tile_radius = (tile_surface_area / f32::consts::PI).sqrt();
+    approximate_tile_radius * 2.0
+}
+
+/// Line segments along every river, as pairs of indices. Rivers start where a tile drains
+/// a thousandth of the planet, or a few tiles on coarse planets.
+pub fn generate_rivers(planet: &Planet) -> (Vec<Vertex>, Vec<u32>) {
+    let min_drainage = (planet.num_tiles() as f32 * 0.001).max(4.0);
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for river in planet.hydrology().rivers(min_drainage) {
+        let path = planet.river_path(&river);
+        let first = vertices.len() as u32;
+        for i in 1..path.len() as u32 {
+            indices.push(first + i - 1);
+            indices.push(first + i);
+        }
+        vertices.extend(path);
+    }
+
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dual_mesh_has_a_fan_per_tile() {
+        let mut gen = Generator::new(3);
+        gen.subdivide();
+        let planet = gen.to_planet();
+
+        let mesh = generate_dual(&planet, false, DualColouring::Elevation);
+
+        let num_corners: usize = planet.tiles_iter().map(|t| t.num_vertices()).sum();
+        assert_eq!(mesh.vertices.len(), num_corners + planet.num_tiles());
+        assert_eq!(mesh.faces.len(), num_corners);
+        assert_eq!(mesh.normals.len(), mesh.vertices.len());
+        assert_eq!(mesh.texcoords.len(), mesh.vertices.len());
+        for f in mesh.faces.iter() {
+            for i in 0..3 {
+                assert!((f[i] as usize) < mesh.vertices.len());
+            }
+        }
+    }
+
+    #[test]
+    fn river_segments_join_consecutive_points() {
+        let mut gen = Generator::new(3);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let mut planet = gen.to_planet();
+        planet.update_surface();
+
+        let (vertices, indices) = generate_rivers(&planet);
+
+        assert!(!indices.is_empty());
+        for segment in indices.chunks(2) {
+            assert_eq!(segment[0] + 1, segment[1]);
+            assert!((segment[1] as usize) < vertices.len());
+        }
+    }
+
+    #[test]
+    fn currents_start_at_ocean_tiles() {
+        let mut gen = Generator::new(3);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let mut planet = gen.to_planet();
+        planet.update_surface();
+
+        let (vertices, indices) = generate_currents(&planet);
+
+        let num_ocean = planet.tiles_iter().filter(|t| planet.is_ocean(t)).count();
+        assert_eq!(indices.len(), 2 * num_ocean);
+        assert_eq!(vertices.len(), indices.len());
+    }
+}
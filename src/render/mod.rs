@@ -1,3 +0,0 @@
-pub use render::wireframe_material::WireframeMaterial;
-
-mod wireframe_material;
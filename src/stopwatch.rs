@@ -0,0 +1,26 @@
+use std::time::{Duration, Instant};
+
+/// Measures wall-clock time for progress logging.
+#[derive(Clone, Copy, Debug)]
+pub struct Stopwatch {
+    start: Instant,
+}
+
+impl Stopwatch {
+    pub fn start_new() -> Stopwatch {
+        Stopwatch { start: Instant::now() }
+    }
+
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn elapsed_ms(&self) -> i64 {
+        let elapsed = self.elapsed();
+        elapsed.as_secs() as i64 * 1000 + elapsed.subsec_nanos() as i64 / 1_000_000
+    }
+}
@@ -0,0 +1,123 @@
+use math::Vec3;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use std::io::{self, Read, Write};
+
+pub fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn write_header<W: Write>(out: &mut W, magic: &[u8; 4], version: u32) -> io::Result<()> {
+    out.write_all(magic)?;
+    out.write_u32::<LittleEndian>(version)
+}
+
+/// Reads and checks the magic bytes, returning the format version if it is supported.
+pub fn read_header<R: Read>(input: &mut R, magic: &[u8; 4], max_version: u32) -> io::Result<u32> {
+    let mut actual = [0u8; 4];
+    input.read_exact(&mut actual)?;
+    if &actual != magic {
+        return Err(invalid_data(format!("Expected magic {:?}, found {:?}", magic, actual)));
+    }
+    let version = input.read_u32::<LittleEndian>()?;
+    if version == 0 || version > max_version {
+        return Err(invalid_data(format!("Unsupported format version {}, expected at most {}",
+                                        version,
+                                        max_version)));
+    }
+    Ok(version)
+}
+
+pub fn write_vec3<W: Write>(out: &mut W, v: &Vec3<f32>) -> io::Result<()> {
+    out.write_f32::<LittleEndian>(v.x)?;
+    out.write_f32::<LittleEndian>(v.y)?;
+    out.write_f32::<LittleEndian>(v.z)
+}
+
+pub fn read_vec3<R: Read>(input: &mut R) -> io::Result<Vec3<f32>> {
+    let x = input.read_f32::<LittleEndian>()?;
+    let y = input.read_f32::<LittleEndian>()?;
+    let z = input.read_f32::<LittleEndian>()?;
+    Ok(Vec3::new(x, y, z))
+}
+
+pub fn write_len<W: Write>(out: &mut W, len: usize) -> io::Result<()> {
+    out.write_u32::<LittleEndian>(len as u32)
+}
+
+pub fn read_len<R: Read>(input: &mut R) -> io::Result<usize> {
+    input.read_u32::<LittleEndian>().map(|n| n as usize)
+}
+
+/// Capacity to reserve for `len` items read from a file. Don't trust the length for
+/// preallocation, the data may be corrupt, so larger lists grow as they are read.
+pub fn preallocation(len: usize) -> usize {
+    len.min(1024)
+}
+
+pub fn write_indices<W: Write>(out: &mut W, indices: &[u32]) -> io::Result<()> {
+    write_len(out, indices.len())?;
+    for i in indices.iter() {
+        out.write_u32::<LittleEndian>(*i)?;
+    }
+    Ok(())
+}
+
+/// Reads a length prefixed list of indices, all of which must be below `limit`.
+pub fn read_indices<R: Read>(input: &mut R, limit: usize) -> io::Result<Vec<u32>> {
+    let len = read_len(input)?;
+    let mut indices = Vec::with_capacity(preallocation(len.min(limit)));
+    for _ in 0..len {
+        indices.push(read_index(input, limit)?);
+    }
+    Ok(indices)
+}
+
+pub fn read_index<R: Read>(input: &mut R, limit: usize) -> io::Result<u32> {
+    let i = input.read_u32::<LittleEndian>()?;
+    if i as usize >= limit {
+        return Err(invalid_data(format!("Index {} out of range, expected below {}", i, limit)));
+    }
+    Ok(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let mut buf = Vec::new();
+        write_header(&mut buf, b"TEST", 2).unwrap();
+
+        assert_eq!(read_header(&mut &buf[..], b"TEST", 3).unwrap(), 2);
+    }
+
+    #[test]
+    fn wrong_magic_is_rejected() {
+        let mut buf = Vec::new();
+        write_header(&mut buf, b"TEST", 1).unwrap();
+
+        let err = read_header(&mut &buf[..], b"ELSE", 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut buf = Vec::new();
+        write_header(&mut buf, b"TEST", 2).unwrap();
+
+        let err = read_header(&mut &buf[..], b"TEST", 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn out_of_range_index_is_rejected() {
+        let mut buf = Vec::new();
+        write_indices(&mut buf, &[1, 2, 5]).unwrap();
+
+        assert!(read_indices(&mut &buf[..], 5).is_err());
+        assert_eq!(read_indices(&mut &buf[..], 6).unwrap(), vec![1, 2, 5]);
+    }
+}
@@ -1,12 +1,13 @@
 mod edge;
 mod face;
 mod node;
+mod persist;
 
 use math::*;
-use terrain::planet::Planet;
+use terrain::planet::{Planet, PlateConfig};
 use terrain::types::{Vertex, Index3};
 
-use rand::{random, thread_rng};
+use rand::Rng;
 use rand::distributions::{IndependentSample, Range};
 use std::f32;
 use std::collections::HashMap;
@@ -22,18 +23,19 @@ pub struct Generator {
     pub faces: Vec<Face>,
     rnd_pow: f32,
     level: u8,
+    seed: u64,
+    rng: TerrainRng,
 }
 
 impl Generator {
-    pub fn new() -> Generator {
+    pub fn new(seed: u64) -> Generator {
+        let mut rng = TerrainRng::new(seed);
         let phi = ((5.0f32).sqrt() + 1.0) / 2.0;
         let du = 1.0 / (phi * phi + 1.0).sqrt();
         let dv = phi * du;
         let z = 0f32;
 
-        fn random_elevation() -> f32 {
-            random::<f32>() * 0.5
-        }
+        let mut random_elevation = || rng.next_f32() * 0.5;
 
         let mut nodes = vec![Node::new(Vertex::new(z, dv, du), random_elevation()),
                              Node::new(Vertex::new(z, dv, -du), random_elevation()),
@@ -109,6 +111,8 @@ impl Generator {
             faces: faces,
             rnd_pow: 3.0,
             level: 0,
+            seed: seed,
+            rng: rng,
         }
 
     }
@@ -151,6 +155,10 @@ impl Generator {
         self.level
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     pub fn num_edges(&self) -> u32 {
         self.edges.len() as u32
     }
@@ -202,7 +210,7 @@ impl Generator {
                 let mid = slerp(&p0.point, &p1.point, 0.5);
                 //let mid = normalize(&p0.point + (&p1.point - &p0.point) / 2.0);
                 let e = (p1.elevation + p0.elevation) / 2.0;
-                (mid, e + rand(0.5) * self.rnd_pow)
+                (mid, e + rand(&mut self.rng, 0.5) * self.rnd_pow)
             };
 
             let vidx = self.nodes.len() as u32;
@@ -418,12 +426,11 @@ impl Generator {
     fn distort(&mut self, degree: u32) -> bool {
         debug!("Distorting to degree {}", degree);
         let num_edges = self.edges.len() as u32;
-        let mut rng = thread_rng();
         let between = Range::new(0, num_edges as u32);
         let mut i = 0;
         while i < degree {
             let mut attempts = 0;
-            let mut edge_index = between.ind_sample(&mut rng);
+            let mut edge_index = between.ind_sample(&mut self.rng);
             while !self.conditional_rotate_edge(edge_index) {
                 attempts += 1;
                 if attempts >= num_edges {
@@ -515,6 +522,12 @@ impl Generator {
     }
 
     pub fn to_planet(&self) -> Planet {
+        self.to_planet_with(PlateConfig::default())
+    }
+
+    /// Builds the planet with plates grown and merged according to `config`. Its climate,
+    /// biomes and drainage are left for `Planet::update_surface` or `Planet::set_surface`.
+    pub fn to_planet_with(&self, config: PlateConfig) -> Planet {
         let num_tiles = self.nodes.len();
         let num_vertices = self.faces.len();
 
@@ -576,35 +589,37 @@ impl Generator {
             borders.push(border);
         }
 
-        Planet::new(vertices, borders)
+        Planet::new(vertices, borders, self.seed, config)
     }
 }
 
-fn rand(max: f32) -> f32 {
-    (random::<f32>() * 2.0 * max) - max
+fn rand<R: Rng>(rng: &mut R, max: f32) -> f32 {
+    (rng.next_f32() * 2.0 * max) - max
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const SEED: u64 = 17;
+
     #[test]
     fn new_terrain_has_edges_for_all_nodes() {
-        let terr = Generator::new();
+        let terr = Generator::new(SEED);
 
         verify_edges_for_nodes(&terr, 5, 5);
     }
 
     #[test]
     fn new_terrain_has_faces_for_all_nodes() {
-        let terr = Generator::new();
+        let terr = Generator::new(SEED);
 
         verify_faces_for_nodes(&terr, 5, 5);
     }
 
     #[test]
     fn new_terrain_has_correct_face_to_edge_linkage() {
-        let terr = Generator::new();
+        let terr = Generator::new(SEED);
 
         verify_faces_for_edges(&terr, 2, 2);
         verify_face_to_edge_link(&terr);
@@ -612,7 +627,7 @@ mod tests {
 
     #[test]
     fn subdivided_terrain_has_edges_for_all_nodes() {
-        let mut terr = Generator::new();
+        let mut terr = Generator::new(SEED);
         terr.subdivide();
         terr.subdivide();
 
@@ -621,7 +636,7 @@ mod tests {
 
     #[test]
     fn subdivided_terrain_has_faces_for_all_nodes() {
-        let mut terr = Generator::new();
+        let mut terr = Generator::new(SEED);
         terr.subdivide();
         terr.subdivide();
 
@@ -630,7 +645,7 @@ mod tests {
 
     #[test]
     fn subdivided_terrain_has_correct_face_to_edge_linkage() {
-        let mut terr = Generator::new();
+        let mut terr = Generator::new(SEED);
         terr.subdivide();
         terr.subdivide();
 
@@ -638,6 +653,62 @@ mod tests {
         verify_face_to_edge_link(&terr);
     }
 
+    #[test]
+    fn same_seed_produces_identical_topology() {
+        let a = generate(SEED, 2, 0.05);
+        let b = generate(SEED, 2, 0.05);
+
+        assert_eq!(a.nodes.len(), b.nodes.len());
+        for (i, (na, nb)) in a.nodes.iter().zip(b.nodes.iter()).enumerate() {
+            assert_eq!(na.point, nb.point, "Node {} position mismatch", i);
+            assert_eq!(na.elevation, nb.elevation, "Node {} elevation mismatch", i);
+            assert_eq!(na.faces, nb.faces, "Node {} face links mismatch", i);
+        }
+        for (i, (fa, fb)) in a.faces.iter().zip(b.faces.iter()).enumerate() {
+            assert_eq!(fa.points, fb.points, "Face {} points mismatch", i);
+            assert_eq!(fa.edges, fb.edges, "Face {} edges mismatch", i);
+        }
+    }
+
+    #[test]
+    fn same_seed_produces_identical_planet() {
+        let a = generate(SEED, 2, 0.05).to_planet();
+        let b = generate(SEED, 2, 0.05).to_planet();
+
+        assert_eq!(a.num_tiles(), b.num_tiles());
+        for (i, (ta, tb)) in a.tiles_iter().zip(b.tiles_iter()).enumerate() {
+            assert_eq!(ta.plate_id, tb.plate_id, "Tile {} plate mismatch", i);
+            assert_eq!(ta.movement_vector,
+                       tb.movement_vector,
+                       "Tile {} movement mismatch",
+                       i);
+            assert_eq!(a.tile_elevation(ta).to_bits(),
+                       b.tile_elevation(tb).to_bits(),
+                       "Tile {} elevation mismatch",
+                       i);
+        }
+    }
+
+    #[test]
+    fn different_seeds_produce_different_planets() {
+        let a = generate(SEED, 2, 0.05).to_planet();
+        let b = generate(SEED + 1, 2, 0.05).to_planet();
+
+        let differs = a.tiles_iter()
+            .zip(b.tiles_iter())
+            .any(|(ta, tb)| a.tile_elevation(ta) != b.tile_elevation(tb));
+        assert!(differs, "Seeds {} and {} gave identical planets", SEED, SEED + 1);
+    }
+
+    fn generate(seed: u64, level: u32, distortion: f32) -> Generator {
+        let mut terr = Generator::new(seed);
+        for _ in 0..level {
+            terr.subdivide();
+        }
+        terr.introduce_chaos(distortion);
+        terr
+    }
+
     fn verify_edges_for_nodes(terr: &Generator, min_edges: u32, max_edges: u32) {
         let num_nodes = terr.nodes.len();
         let mut seen_nodes = vec![Vec::new(); num_nodes];
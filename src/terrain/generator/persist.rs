@@ -0,0 +1,185 @@
+use math::TerrainRng;
+use terrain::codec::*;
+use terrain::types::Index3;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::{Generator, Node, Edge, Face};
+
+const MAGIC: &[u8; 4] = b"TGGN";
+const VERSION: u32 = 1;
+
+impl Generator {
+    /// Writes the triangle mesh with all its links, together with the random generator
+    /// state, so that a loaded generator continues exactly where this one left off.
+    pub fn save<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_header(out, MAGIC, VERSION)?;
+
+        out.write_u64::<LittleEndian>(self.seed)?;
+        for word in self.rng.state().iter() {
+            out.write_u32::<LittleEndian>(*word)?;
+        }
+        out.write_u8(self.level)?;
+        out.write_f32::<LittleEndian>(self.rnd_pow)?;
+
+        write_len(out, self.nodes.len())?;
+        write_len(out, self.edges.len())?;
+        write_len(out, self.faces.len())?;
+
+        for node in self.nodes.iter() {
+            write_vec3(out, &node.point)?;
+            out.write_f32::<LittleEndian>(node.elevation)?;
+            write_indices(out, &node.faces)?;
+            write_indices(out, &node.edges)?;
+        }
+        for edge in self.edges.iter() {
+            out.write_u32::<LittleEndian>(edge.a)?;
+            out.write_u32::<LittleEndian>(edge.b)?;
+            write_indices(out, &edge.faces)?;
+        }
+        for face in self.faces.iter() {
+            write_index3(out, &face.points)?;
+            write_index3(out, &face.edges)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load<R: Read>(input: &mut R) -> io::Result<Generator> {
+        read_header(input, MAGIC, VERSION)?;
+
+        let seed = input.read_u64::<LittleEndian>()?;
+        let mut state = [0u32; 4];
+        for word in state.iter_mut() {
+            *word = input.read_u32::<LittleEndian>()?;
+        }
+        let rng = TerrainRng::from_state(state)
+            .ok_or_else(|| invalid_data("Invalid random generator state".to_string()))?;
+        let level = input.read_u8()?;
+        let rnd_pow = input.read_f32::<LittleEndian>()?;
+
+        let num_nodes = read_len(input)?;
+        let num_edges = read_len(input)?;
+        let num_faces = read_len(input)?;
+
+        let mut nodes = Vec::with_capacity(preallocation(num_nodes));
+        for _ in 0..num_nodes {
+            let point = read_vec3(input)?;
+            let elevation = input.read_f32::<LittleEndian>()?;
+            let mut node = Node::new(point, elevation);
+            node.faces = read_indices(input, num_faces)?;
+            node.edges = read_indices(input, num_edges)?;
+            nodes.push(node);
+        }
+        let mut edges = Vec::with_capacity(preallocation(num_edges));
+        for _ in 0..num_edges {
+            let a = read_index(input, num_nodes)?;
+            let b = read_index(input, num_nodes)?;
+            let mut edge = Edge::new(a, b);
+            edge.faces = read_indices(input, num_faces)?;
+            edges.push(edge);
+        }
+        let mut faces = Vec::with_capacity(preallocation(num_faces));
+        for _ in 0..num_faces {
+            let points = read_index3(input, num_nodes)?;
+            let face_edges = read_index3(input, num_edges)?;
+            faces.push(Face::new(points, face_edges));
+        }
+
+        Ok(Generator {
+            nodes,
+            edges,
+            faces,
+            rnd_pow,
+            level,
+            seed,
+            rng,
+        })
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.save(&mut out)?;
+        out.flush()
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> io::Result<Generator> {
+        Generator::load(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+fn write_index3<W: Write>(out: &mut W, index: &Index3) -> io::Result<()> {
+    out.write_u32::<LittleEndian>(index.x)?;
+    out.write_u32::<LittleEndian>(index.y)?;
+    out.write_u32::<LittleEndian>(index.z)
+}
+
+fn read_index3<R: Read>(input: &mut R, limit: usize) -> io::Result<Index3> {
+    let x = read_index(input, limit)?;
+    let y = read_index(input, limit)?;
+    let z = read_index(input, limit)?;
+    Ok(Index3::new(x, y, z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint() -> Generator {
+        let mut gen = Generator::new(31);
+        gen.subdivide();
+        gen.subdivide();
+        gen.introduce_chaos(0.05);
+        gen
+    }
+
+    #[test]
+    fn generator_round_trip() {
+        let gen = checkpoint();
+        let mut buf = Vec::new();
+        gen.save(&mut buf).unwrap();
+
+        let loaded = Generator::load(&mut &buf[..]).unwrap();
+
+        assert_eq!(loaded.current_level(), gen.current_level());
+        assert_eq!(loaded.seed(), gen.seed());
+        let mut resaved = Vec::new();
+        loaded.save(&mut resaved).unwrap();
+        assert!(resaved == buf, "Saving a loaded generator should give identical bytes");
+    }
+
+    #[test]
+    fn resumed_generator_continues_like_the_original() {
+        let mut original = checkpoint();
+        let mut buf = Vec::new();
+        original.save(&mut buf).unwrap();
+        let mut resumed = Generator::load(&mut &buf[..]).unwrap();
+
+        original.subdivide();
+        original.introduce_chaos(0.05);
+        resumed.subdivide();
+        resumed.introduce_chaos(0.05);
+
+        assert_eq!(original.nodes.len(), resumed.nodes.len());
+        for (a, b) in original.nodes.iter().zip(resumed.nodes.iter()) {
+            assert_eq!(a.point, b.point);
+            assert_eq!(a.elevation, b.elevation);
+        }
+        for (a, b) in original.faces.iter().zip(resumed.faces.iter()) {
+            assert_eq!(a.points, b.points);
+        }
+    }
+
+    #[test]
+    fn planet_file_is_not_a_generator() {
+        let gen = checkpoint();
+        let mut buf = Vec::new();
+        gen.to_planet().save(&mut buf).unwrap();
+
+        assert!(Generator::load(&mut &buf[..]).is_err());
+    }
+}
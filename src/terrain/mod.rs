@@ -1,3 +1,4 @@
 pub mod generator;
 pub mod planet;
 pub mod types;
+mod codec;
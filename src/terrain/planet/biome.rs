@@ -0,0 +1,248 @@
+use std::f32;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use super::{Planet, Tile};
+
+// The rules in media/biomes.txt
+const DEFAULT_RULES: &str = include_str!("../../../media/biomes.txt");
+
+/// Kinds of land cover and sea, roughly after Whittaker's biome diagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Biome {
+    DeepOcean,
+    ShallowSea,
+    SeaIce,
+    Ice,
+    Tundra,
+    Taiga,
+    TemperateForest,
+    TemperateRainforest,
+    Grassland,
+    Desert,
+    Savanna,
+    SeasonalForest,
+    Rainforest,
+}
+
+pub const BIOMES: [Biome; 13] = [Biome::DeepOcean,
+                                 Biome::ShallowSea,
+                                 Biome::SeaIce,
+                                 Biome::Ice,
+                                 Biome::Tundra,
+                                 Biome::Taiga,
+                                 Biome::TemperateForest,
+                                 Biome::TemperateRainforest,
+                                 Biome::Grassland,
+                                 Biome::Desert,
+                                 Biome::Savanna,
+                                 Biome::SeasonalForest,
+                                 Biome::Rainforest];
+
+impl Biome {
+    /// Name used in rule files, `deep_ocean`, `taiga` and so on.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Biome::DeepOcean => "deep_ocean",
+            Biome::ShallowSea => "shallow_sea",
+            Biome::SeaIce => "sea_ice",
+            Biome::Ice => "ice",
+            Biome::Tundra => "tundra",
+            Biome::Taiga => "taiga",
+            Biome::TemperateForest => "temperate_forest",
+            Biome::TemperateRainforest => "temperate_rainforest",
+            Biome::Grassland => "grassland",
+            Biome::Desert => "desert",
+            Biome::Savanna => "savanna",
+            Biome::SeasonalForest => "seasonal_forest",
+            Biome::Rainforest => "rainforest",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Biome> {
+        BIOMES.iter().find(|b| b.name() == name).cloned()
+    }
+
+    /// Position in `BIOMES`.
+    pub fn index(&self) -> usize {
+        BIOMES.iter().position(|b| b == self).unwrap()
+    }
+}
+
+/// Classifies tiles with the first rule whose ranges hold their temperature, precipitation
+/// and elevation above sea level. Each range includes its lower bound but not its upper
+/// one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeRule {
+    pub biome: Biome,
+    pub temperature: (f32, f32),
+    pub precipitation: (f32, f32),
+    pub elevation: (f32, f32),
+}
+
+impl BiomeRule {
+    pub fn matches(&self, temperature: f32, precipitation: f32, elevation: f32) -> bool {
+        within(self.temperature, temperature) && within(self.precipitation, precipitation) &&
+        within(self.elevation, elevation)
+    }
+}
+
+fn within(range: (f32, f32), value: f32) -> bool {
+    range.0 <= value && value < range.1
+}
+
+/// Ordered table of biome rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeRules {
+    pub rules: Vec<BiomeRule>,
+}
+
+impl Default for BiomeRules {
+    /// The rules in `media/biomes.txt`.
+    fn default() -> BiomeRules {
+        BiomeRules::parse(DEFAULT_RULES).unwrap()
+    }
+}
+
+impl BiomeRules {
+    /// Reads a rule table like `media/biomes.txt`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<BiomeRules> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        BiomeRules::parse(&text)
+    }
+
+    /// Parses one rule per line: a biome name followed by ranges of temperature,
+    /// precipitation and elevation written as `MIN..MAX`, with either end optional.
+    /// Empty lines and everything after a `#` are skipped.
+    pub fn parse(text: &str) -> io::Result<BiomeRules> {
+        let mut rules = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |message: String| {
+                io::Error::new(io::ErrorKind::InvalidData,
+                               format!("Line {}: {}", number + 1, message))
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 4 {
+                return Err(invalid(format!("Expected a biome and 3 ranges, found {} fields",
+                                           fields.len())));
+            }
+            let biome = Biome::from_name(fields[0])
+                .ok_or_else(|| invalid(format!("Unknown biome {}", fields[0])))?;
+            let mut ranges = Vec::with_capacity(3);
+            for field in fields[1..].iter() {
+                ranges.push(parse_range(field)
+                    .ok_or_else(|| invalid(format!("Invalid range {}", field)))?);
+            }
+            rules.push(BiomeRule {
+                biome,
+                temperature: ranges[0],
+                precipitation: ranges[1],
+                elevation: ranges[2],
+            });
+        }
+        Ok(BiomeRules { rules })
+    }
+
+    /// Biome of the first matching rule, if any.
+    pub fn classify(&self, temperature: f32, precipitation: f32, elevation: f32) -> Option<Biome> {
+        self.rules
+            .iter()
+            .find(|r| r.matches(temperature, precipitation, elevation))
+            .map(|r| r.biome)
+    }
+}
+
+fn parse_range(text: &str) -> Option<(f32, f32)> {
+    let (min, max) = text.split_once("..")?;
+    let bound = |s: &str, open: f32| if s.is_empty() { Some(open) } else { s.parse().ok() };
+    Some((bound(min, f32::NEG_INFINITY)?, bound(max, f32::INFINITY)?))
+}
+
+impl Planet {
+    pub fn biome_rules(&self) -> &BiomeRules {
+        &self.biome_rules
+    }
+
+    /// Changes the biome rules, taking effect on the next `update_surface`.
+    pub fn set_biome_rules(&mut self, rules: BiomeRules) {
+        self.biome_rules = rules;
+    }
+
+    /// Biome of the tile, none if no rule matches it.
+    pub fn tile_biome(&self, tile: &Tile) -> Option<Biome> {
+        self.biomes[self.tile_index(tile)]
+    }
+
+    pub(super) fn update_biomes(&mut self) {
+        self.biomes = self.tiles
+            .iter()
+            .map(|t| {
+                self.biome_rules.classify(self.tile_temperature(t),
+                                          self.tile_precipitation(t),
+                                          self.tile_elevation(t) - self.sea_level)
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_rules_follow_the_biome_diagram() {
+        let rules = BiomeRules::default();
+
+        assert_eq!(rules.classify(15.0, 500.0, -3000.0), Some(Biome::DeepOcean));
+        assert_eq!(rules.classify(15.0, 500.0, -50.0), Some(Biome::ShallowSea));
+        assert_eq!(rules.classify(-20.0, 100.0, -3000.0), Some(Biome::SeaIce));
+        assert_eq!(rules.classify(-20.0, 100.0, 1000.0), Some(Biome::Ice));
+        assert_eq!(rules.classify(-7.0, 300.0, 200.0), Some(Biome::Tundra));
+        assert_eq!(rules.classify(0.0, 600.0, 200.0), Some(Biome::Taiga));
+        assert_eq!(rules.classify(12.0, 1000.0, 200.0), Some(Biome::TemperateForest));
+        assert_eq!(rules.classify(12.0, 300.0, 200.0), Some(Biome::Grassland));
+        assert_eq!(rules.classify(25.0, 50.0, 200.0), Some(Biome::Desert));
+        assert_eq!(rules.classify(25.0, 600.0, 200.0), Some(Biome::Savanna));
+        assert_eq!(rules.classify(25.0, 3000.0, 200.0), Some(Biome::Rainforest));
+    }
+
+    #[test]
+    fn parses_open_ranges_and_comments() {
+        let rules = BiomeRules::parse("# comment\n\
+                                       \n\
+                                       ice ..0 .. ..  # frozen\n\
+                                       desert 0.. ..250.5 -10..\n")
+            .unwrap();
+
+        assert_eq!(rules.rules.len(), 2);
+        assert_eq!(rules.rules[1],
+                   BiomeRule {
+                       biome: Biome::Desert,
+                       temperature: (0.0, f32::INFINITY),
+                       precipitation: (f32::NEG_INFINITY, 250.5),
+                       elevation: (-10.0, f32::INFINITY),
+                   });
+        assert_eq!(rules.classify(20.0, 300.0, 0.0), None);
+    }
+
+    #[test]
+    fn rejects_unknown_biomes_and_bad_ranges() {
+        assert!(BiomeRules::parse("jungle .. .. ..").is_err());
+        assert!(BiomeRules::parse("desert .. 250 ..").is_err());
+        assert!(BiomeRules::parse("desert .. ..").is_err());
+    }
+
+    #[test]
+    fn every_biome_has_a_unique_name() {
+        for (i, biome) in BIOMES.iter().enumerate() {
+            assert_eq!(Biome::from_name(biome.name()), Some(*biome));
+            assert_eq!(biome.index(), i);
+        }
+    }
+}
@@ -1,11 +1,63 @@
-use math::sorted_pair;
+use math::{Vec3, DotProduct};
+use math::{normalize, sorted_pair};
 
 use terrain::planet::{VertexIndex, TileIndex};
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoundaryType {
+    /// The plates move towards each other
+    Convergent,
+    /// The plates move apart
+    Divergent,
+    /// The plates mostly slide past each other
+    Transform,
+}
+
+/// Relative movement of two plates across a border.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Boundary {
+    pub boundary_type: BoundaryType,
+    /// Speed towards each other across the border, negative when moving apart
+    pub pressure: f32,
+    /// Speed along the border, always positive
+    pub shear: f32,
+}
+
+impl Boundary {
+    /// Classifies a border from the midpoints and movement vectors of the tiles on
+    /// either side of it. Whichever of pressure and shear is larger decides the type.
+    pub fn from_movement(midpoint_a: &Vec3<f32>,
+                         movement_a: &Vec3<f32>,
+                         midpoint_b: &Vec3<f32>,
+                         movement_b: &Vec3<f32>)
+                         -> Boundary {
+        let normal = normalize(midpoint_b - midpoint_a);
+        let relative = movement_a - movement_b;
+        let pressure = relative.dot(&normal);
+        let shear = (&relative - &normal * pressure).length();
+
+        let boundary_type = if shear > pressure.abs() {
+            BoundaryType::Transform
+        } else if pressure > 0.0 {
+            BoundaryType::Convergent
+        } else {
+            BoundaryType::Divergent
+        };
+
+        Boundary {
+            boundary_type: boundary_type,
+            pressure: pressure,
+            shear: shear,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Border {
     pub vertices: (VertexIndex, VertexIndex),
     pub tiles: (TileIndex, TileIndex),
+    /// Set for borders between two different plates
+    pub boundary: Option<Boundary>,
 }
 
 impl Border {
@@ -13,6 +65,7 @@ impl Border {
         Border {
             vertices: sorted_pair(va, vb),
             tiles: sorted_pair(ta, tb),
+            boundary: None,
         }
     }
 
@@ -26,3 +79,40 @@ impl Border {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(movement_a: Vec3<f32>, movement_b: Vec3<f32>) -> Boundary {
+        let a = Vec3::new(0.0, 0.0, 1.0);
+        let b = normalize(Vec3::new(0.1, 0.0, 1.0));
+        Boundary::from_movement(&a, &movement_a, &b, &movement_b)
+    }
+
+    #[test]
+    fn plates_moving_together_converge() {
+        let boundary = classify(Vec3::new(0.2, 0.0, 0.0), Vec3::new(-0.1, 0.0, 0.0));
+
+        assert_eq!(boundary.boundary_type, BoundaryType::Convergent);
+        assert!(boundary.pressure > 0.29);
+        assert!(boundary.shear < 0.1);
+    }
+
+    #[test]
+    fn plates_moving_apart_diverge() {
+        let boundary = classify(Vec3::new(-0.2, 0.0, 0.0), Vec3::new(0.0, 0.05, 0.0));
+
+        assert_eq!(boundary.boundary_type, BoundaryType::Divergent);
+        assert!(boundary.pressure < -0.19);
+    }
+
+    #[test]
+    fn plates_sliding_past_each_other_transform() {
+        let boundary = classify(Vec3::new(0.0, 0.3, 0.0), Vec3::new(0.0, -0.3, 0.0));
+
+        assert_eq!(boundary.boundary_type, BoundaryType::Transform);
+        assert!((boundary.shear - 0.6).abs() < 1e-5);
+        assert!(boundary.pressure.abs() < 1e-5);
+    }
+}
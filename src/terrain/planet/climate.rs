@@ -0,0 +1,529 @@
+use math::{DotProduct, Vec3, normalize};
+
+use std::collections::VecDeque;
+use std::f32;
+use std::f32::consts::FRAC_PI_2;
+
+use super::{Planet, Tile, TileIndex, Vertex};
+use super::ocean::{self, FREEZING_POINT};
+
+/// Settings for the climate of a planet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClimateConfig {
+    /// Angle in degrees between the rotation axis and the orbital plane's normal
+    pub axial_tilt: f32,
+    /// Mean sea level temperature in °C at the equator, on a planet tilted like Earth
+    pub equator_temperature: f32,
+    /// Mean sea level temperature in °C at the poles, on a planet tilted like Earth
+    pub pole_temperature: f32,
+    /// Cooling in °C per km of elevation
+    pub lapse_rate: f32,
+    /// Share of the difference to the global mean temperature that the sea evens out
+    pub ocean_moderation: f32,
+    /// Distance in km from the coast where the sea no longer sets the temperature on land
+    pub moderation_distance: f32,
+    /// Water in mm a year that evaporates from a sea at 25 °C, less from colder seas
+    pub ocean_evaporation: f32,
+    /// Distance in km over which air that neither rises nor sinks rains out all but a
+    /// third of its moisture
+    pub rain_distance: f32,
+    /// Climb in m that forces air to rain out all of its moisture
+    pub orographic_height: f32,
+    /// Share of the precipitation on land that evaporates again and moves on with the wind
+    pub recycling: f32,
+}
+
+impl Default for ClimateConfig {
+    fn default() -> ClimateConfig {
+        ClimateConfig {
+            axial_tilt: 23.44,
+            equator_temperature: 27.0,
+            pole_temperature: -30.0,
+            lapse_rate: 6.5,
+            ocean_moderation: 0.2,
+            moderation_distance: 1000.0,
+            ocean_evaporation: 1400.0,
+            rain_distance: 2000.0,
+            orographic_height: 1500.0,
+            recycling: 0.6,
+        }
+    }
+}
+
+const EARTH_AXIAL_TILT: f32 = 23.44;
+
+/// Annual mean climate of every tile.
+#[derive(Clone, Debug, Default)]
+pub struct Climate {
+    /// Of the air in °C
+    pub temperature: Vec<f32>,
+    /// Of the water at the surface of ocean tiles in °C, the air temperature on land
+    pub sea_temperature: Vec<f32>,
+    /// In km to the nearest ocean tile, 0 at sea and infinite on planets without oceans
+    pub coast_distance: Vec<f32>,
+    /// Prevailing surface wind, along the surface and at most 1 at the strongest
+    pub wind: Vec<Vertex>,
+    /// Ocean surface current, along the surface as strong as the winds and zero on land
+    pub current: Vec<Vertex>,
+    /// In mm a year
+    pub precipitation: Vec<f32>,
+}
+
+impl Climate {
+    /// Computes the climate from the direction from the planet's centre to each tile and
+    /// its elevation, with tiles `tile_km` apart and the ocean below `sea_level`.
+    pub fn new(normals: &[Vertex],
+               elevations: &[f32],
+               neighbours: &[Vec<TileIndex>],
+               tile_km: f32,
+               sea_level: f32,
+               config: &ClimateConfig)
+               -> Climate {
+        let (coast_distance, nearest_ocean) =
+            coast_distances(elevations, neighbours, tile_km, sea_level);
+
+        // Temperatures follow the sunlight, calibrated so that a planet tilted like Earth
+        // gets the configured temperatures at the equator and the poles
+        let earth = insolation_shape(EARTH_AXIAL_TILT);
+        let per_insolation = (config.equator_temperature - config.pole_temperature) /
+                             (insolation(0.0, earth) - insolation(1.0, earth));
+        let to_temperature =
+            |s: f32| config.pole_temperature + per_insolation * (s - insolation(1.0, earth));
+        // The sunlight averages to 1 over the sphere whatever the tilt
+        let mean = to_temperature(1.0);
+        let shape = insolation_shape(config.axial_tilt);
+        let zonal: Vec<f32> = normals.iter()
+            .map(|n| to_temperature(insolation(n.y, shape)))
+            .collect();
+
+        let wind: Vec<Vertex> = normals.iter().map(prevailing_wind).collect();
+        let current = ocean::currents(normals, elevations, neighbours, sea_level, &wind);
+
+        // The sea evens out the temperatures at each latitude, and currents carry that
+        // water along
+        let moderated: Vec<f32> =
+            zonal.iter().map(|z| z + config.ocean_moderation * (mean - z)).collect();
+        let water = ocean::sea_temperatures(normals,
+                                            elevations,
+                                            neighbours,
+                                            sea_level,
+                                            &current,
+                                            &moderated,
+                                            tile_km);
+
+        // Land near the coast takes after the sea next to it
+        let temperature: Vec<f32> = (0..normals.len())
+            .map(|t| {
+                let at_sea_level = match nearest_ocean[t] {
+                    Some(o) => {
+                        let continentality =
+                            (coast_distance[t] / config.moderation_distance).min(1.0);
+                        zonal[t] + (1.0 - continentality) * (water[o] - zonal[o])
+                    }
+                    None => zonal[t],
+                };
+                let height = (elevations[t] - sea_level).max(0.0);
+                at_sea_level - config.lapse_rate * height / 1000.0
+            })
+            .collect();
+        let sea_temperature: Vec<f32> = (0..normals.len())
+            .map(|t| if elevations[t] < sea_level {
+                water[t].max(FREEZING_POINT)
+            } else {
+                temperature[t]
+            })
+            .collect();
+
+        let terrain = Terrain {
+            normals,
+            elevations,
+            neighbours,
+            tile_km,
+            sea_level,
+        };
+        let precipitation = precipitation(&terrain, &sea_temperature, &wind, config);
+
+        Climate {
+            temperature,
+            sea_temperature,
+            coast_distance,
+            wind,
+            current,
+            precipitation,
+        }
+    }
+}
+
+/// The tiles that moisture moves across, with the ocean below `sea_level`.
+struct Terrain<'a> {
+    normals: &'a [Vertex],
+    elevations: &'a [f32],
+    neighbours: &'a [Vec<TileIndex>],
+    tile_km: f32,
+    sea_level: f32,
+}
+
+/// Number of passes over all tiles that moisture gets to settle.
+const PRECIPITATION_SWEEPS: usize = 4;
+
+/// Eastward and northward unit vectors along the surface at `normal`, both zero at the
+/// poles.
+fn east_north(normal: &Vertex) -> (Vertex, Vertex) {
+    let east = normalize(normal.cross(&Vec3::new(0.0, 1.0, 0.0)));
+    let north = east.cross(normal);
+    (east, north)
+}
+
+/// Surface wind of the three circulation cells in each hemisphere on a planet spinning
+/// like Earth: easterly trade winds blowing towards the equator up to 30°, westerlies
+/// blowing towards the poles up to 60° and polar easterlies beyond. The wind is calm
+/// where the cells meet.
+fn prevailing_wind(normal: &Vertex) -> Vertex {
+    let latitude = normal.y.clamp(-1.0, 1.0).asin();
+    let eastward = -(6.0 * latitude.abs()).sin();
+    let northward = -0.5 * (6.0 * latitude).sin();
+    let (east, north) = east_north(normal);
+    east * eastward + north * northward
+}
+
+/// Distance in radians along the prevailing wind, from an arbitrary start, that orders
+/// tiles from upwind to downwind within each circulation cell.
+fn upwind_key(normal: &Vertex) -> f32 {
+    let latitude = normal.y.clamp(-1.0, 1.0).asin();
+    let longitude = normal.z.atan2(normal.x);
+    let eastward = -(6.0 * latitude.abs()).sin();
+    let northward = -(6.0 * latitude).sin();
+    eastward.signum() * longitude * latitude.cos() + northward.signum() * latitude
+}
+
+/// Share of the moisture in the air that rains out over a tile, most where the cells
+/// lift the air at the equator and around 60°, and least where it sinks around 30° and
+/// at the poles.
+fn rain_rate(normal: &Vertex, tile_km: f32, config: &ClimateConfig) -> f32 {
+    let latitude = normal.y.clamp(-1.0, 1.0).asin();
+    let lift = (1.5 * (6.0 * latitude).cos()).exp();
+    // Distance is in units where moisture falls to a third, about e^-1.1
+    1.0 - (-1.1 * lift * tile_km / config.rain_distance).exp()
+}
+
+/// Annual precipitation from moisture that evaporates from the sea and drifts
+/// downwind from tile to tile, raining out as it goes and all the more when it has to
+/// climb. Little is left for the lee side of mountains.
+fn precipitation(terrain: &Terrain,
+                 sea_temperature: &[f32],
+                 wind: &[Vertex],
+                 config: &ClimateConfig)
+                 -> Vec<f32> {
+    let Terrain { normals, elevations, neighbours, tile_km, sea_level } = *terrain;
+    let num_tiles = normals.len();
+    let ground = |t: usize| elevations[t].max(sea_level);
+
+    // Sweeping the tiles in order from upwind to downwind carries moisture across many
+    // tiles at once. Moisture only moves forward in that order, or across the jump in
+    // order where a circulation cell wraps around the globe, which waits for the next
+    // sweep.
+    let keys: Vec<f32> = normals.iter().map(upwind_key).collect();
+    let mut order: Vec<usize> = (0..num_tiles).collect();
+    order.sort_by(|a, b| keys[*a].partial_cmp(&keys[*b]).unwrap());
+    let forward = |t: usize, n: usize| keys[n] > keys[t] || keys[t] - keys[n] > FRAC_PI_2;
+    let ahead = |t: usize| {
+        let ahead: Vec<usize> = neighbours[t]
+            .iter()
+            .map(|n| *n as usize)
+            .filter(|n| forward(t, *n))
+            .collect();
+        if ahead.is_empty() {
+            // Air caught where the order turns goes anywhere, next sweep
+            neighbours[t].iter().map(|n| *n as usize).collect()
+        } else {
+            ahead
+        }
+    };
+
+    // Where the moisture leaving each tile goes: neighbour, share, and the part of the
+    // share that rains out climbing to it
+    let mut downwind = Vec::with_capacity(num_tiles);
+    for t in 0..num_tiles {
+        let mut targets: Vec<(usize, f32, f32)> = ahead(t)
+            .into_iter()
+            .map(|n| {
+                let towards = normalize(&normals[n] - &normals[t]);
+                let climb = ((ground(n) - ground(t)) / config.orographic_height).max(0.0);
+                (n, wind[t].dot(&towards).max(0.0), climb.min(1.0))
+            })
+            .collect();
+        let total: f32 = targets.iter().map(|&(_, w, _)| w).sum();
+        let count = targets.len() as f32;
+        for target in targets.iter_mut() {
+            // Calm air drifts evenly onwards
+            target.1 = if total > 1e-6 { target.1 / total } else { 1.0 / count };
+        }
+        downwind.push(targets);
+    }
+
+    let rates: Vec<f32> = normals.iter().map(|n| rain_rate(n, tile_km, config)).collect();
+    let evaporation: Vec<f32> = (0..num_tiles)
+        .map(|t| if elevations[t] < sea_level {
+            config.ocean_evaporation * ((sea_temperature[t] + 5.0) / 30.0).max(0.0)
+        } else {
+            0.0
+        })
+        .collect();
+
+    let mut precipitation = vec![0.0; num_tiles];
+    let mut inflow = vec![0.0; num_tiles];
+    for _ in 0..PRECIPITATION_SWEEPS {
+        for &t in order.iter() {
+            let moisture = evaporation[t] + inflow[t];
+            inflow[t] = 0.0;
+            let mut rain = moisture * rates[t];
+            let carried = moisture - rain;
+            for &(n, share, climb) in downwind[t].iter() {
+                let part = carried * share;
+                rain += part * climb;
+                inflow[n] += part * (1.0 - climb);
+            }
+            if elevations[t] >= sea_level {
+                // Water evaporating from the land joins the air moving on
+                for &(n, share, _) in downwind[t].iter() {
+                    inflow[n] += config.recycling * rain * share;
+                }
+            }
+            precipitation[t] = rain;
+        }
+    }
+    precipitation
+}
+
+/// Second Legendre polynomial.
+fn p2(x: f32) -> f32 {
+    (3.0 * x * x - 1.0) / 2.0
+}
+
+/// Coefficient of the annual mean sunlight by latitude for an axial tilt in degrees,
+/// after North's (1975) approximation 1 + s2 P2(sin latitude).
+fn insolation_shape(axial_tilt: f32) -> f32 {
+    -0.625 * p2(axial_tilt.to_radians().cos())
+}
+
+/// Annual mean sunlight relative to the planet's average, at `x` the sine of the latitude.
+fn insolation(x: f32, shape: f32) -> f32 {
+    1.0 + shape * p2(x)
+}
+
+/// Distance from each tile to the nearest ocean tile, counted in steps between
+/// neighbouring tiles, and which ocean tile that is.
+fn coast_distances(elevations: &[f32],
+                   neighbours: &[Vec<TileIndex>],
+                   tile_km: f32,
+                   sea_level: f32)
+                   -> (Vec<f32>, Vec<Option<usize>>) {
+    let mut distance = vec![f32::INFINITY; elevations.len()];
+    let mut nearest = vec![None; elevations.len()];
+    let mut queue = VecDeque::new();
+    for (t, e) in elevations.iter().enumerate() {
+        if *e < sea_level {
+            distance[t] = 0.0;
+            nearest[t] = Some(t);
+            queue.push_back(t);
+        }
+    }
+    while let Some(t) = queue.pop_front() {
+        for n in neighbours[t].iter() {
+            let n = *n as usize;
+            if distance[n] == f32::INFINITY {
+                distance[n] = distance[t] + tile_km;
+                nearest[n] = nearest[t];
+                queue.push_back(n);
+            }
+        }
+    }
+    (distance, nearest)
+}
+
+impl Planet {
+    pub fn climate(&self) -> &Climate {
+        &self.climate
+    }
+
+    pub fn climate_config(&self) -> &ClimateConfig {
+        &self.climate_config
+    }
+
+    /// Changes the climate settings, taking effect on the next `update_surface`.
+    pub fn set_climate_config(&mut self, config: ClimateConfig) {
+        self.climate_config = config;
+    }
+
+    /// Latitude of the tile midpoint in radians, with the poles along the y axis.
+    pub fn tile_latitude(&self, tile: &Tile) -> f32 {
+        self.tile_normal(tile).y.clamp(-1.0, 1.0).asin()
+    }
+
+    /// Annual mean temperature of the tile in °C.
+    pub fn tile_temperature(&self, tile: &Tile) -> f32 {
+        self.climate.temperature[self.tile_index(tile)]
+    }
+
+    /// Annual precipitation on the tile in mm.
+    pub fn tile_precipitation(&self, tile: &Tile) -> f32 {
+        self.climate.precipitation[self.tile_index(tile)]
+    }
+
+    pub fn tile_wind(&self, tile: &Tile) -> &Vertex {
+        &self.climate.wind[self.tile_index(tile)]
+    }
+
+    pub(super) fn update_climate(&mut self) {
+        let normals: Vec<Vertex> = self.tiles.iter().map(|t| self.tile_normal(t)).collect();
+        let elevations: Vec<f32> = self.tiles.iter().map(|t| self.tile_elevation(t)).collect();
+        self.climate = Climate::new(&normals,
+                                    &elevations,
+                                    &self.tile_neighbours,
+                                    self.tile_size_km(),
+                                    self.sea_level,
+                                    &self.climate_config);
+        self.update_biomes();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f32::consts::PI;
+
+    /// Tiles in a row, each a neighbour of the next, and the last of the first if they
+    /// close a ring.
+    fn chain(n: usize, ring: bool) -> Vec<Vec<TileIndex>> {
+        (0..n)
+            .map(|i| {
+                let mut ns = Vec::new();
+                if i > 0 || ring {
+                    ns.push(((i + n - 1) % n) as TileIndex);
+                }
+                if i + 1 < n || ring {
+                    ns.push(((i + 1) % n) as TileIndex);
+                }
+                ns
+            })
+            .collect()
+    }
+
+    /// Tiles in a row from the south pole to the north pole.
+    fn meridian(elevations: &[f32]) -> (Vec<Vertex>, Vec<Vec<TileIndex>>) {
+        let n = elevations.len();
+        let normals = (0..n)
+            .map(|i| {
+                let latitude = -FRAC_PI_2 + PI * i as f32 / (n - 1) as f32;
+                Vec3::new(latitude.cos(), latitude.sin(), 0.0)
+            })
+            .collect();
+        (normals, chain(n, false))
+    }
+
+    /// Tiles around a circle of latitude, eastwards.
+    fn parallel(latitude: f32, n: usize) -> (Vec<Vertex>, Vec<Vec<TileIndex>>) {
+        let latitude = latitude.to_radians();
+        let normals = (0..n)
+            .map(|i| {
+                let longitude = 2.0 * PI * i as f32 / n as f32;
+                Vec3::new(latitude.cos() * longitude.cos(),
+                          latitude.sin(),
+                          latitude.cos() * longitude.sin())
+            })
+            .collect();
+        (normals, chain(n, true))
+    }
+
+    #[test]
+    fn earth_like_tilt_gives_configured_extremes() {
+        let elevations = [-100.0; 5];
+        let (normals, neighbours) = meridian(&elevations);
+        let config = ClimateConfig { ocean_moderation: 0.0, ..ClimateConfig::default() };
+        // Apart, so that no currents carry water between them
+        let apart = vec![Vec::new(); neighbours.len()];
+
+        let climate = Climate::new(&normals, &elevations, &apart, 100.0, 0.0, &config);
+
+        assert!((climate.temperature[0] - config.pole_temperature).abs() < 0.01);
+        assert!((climate.temperature[2] - config.equator_temperature).abs() < 0.01);
+        assert!((climate.temperature[4] - config.pole_temperature).abs() < 0.01);
+        assert!(climate.temperature[1] > climate.temperature[0]);
+    }
+
+    #[test]
+    fn tilted_over_planet_has_warm_poles() {
+        let elevations = [-100.0; 5];
+        let (normals, neighbours) = meridian(&elevations);
+        let config = ClimateConfig { axial_tilt: 90.0, ..ClimateConfig::default() };
+
+        let climate = Climate::new(&normals, &elevations, &neighbours, 100.0, 0.0, &config);
+
+        assert!(climate.temperature[0] > climate.temperature[2]);
+    }
+
+    #[test]
+    fn mountains_and_inland_are_colder() {
+        // Ocean at the south pole, the rest land rising to a peak at the equator
+        let elevations = [-100.0, 0.0, 3000.0, 0.0, 0.0];
+        let (normals, neighbours) = meridian(&elevations);
+        let config = ClimateConfig::default();
+
+        let climate = Climate::new(&normals, &elevations, &neighbours, 500.0, 0.0, &config);
+        let flat = Climate::new(&normals, &[0.0; 5], &neighbours, 500.0, 0.0, &config);
+
+        assert_eq!(climate.coast_distance, vec![0.0, 500.0, 1000.0, 1500.0, 2000.0]);
+        assert!((flat.temperature[2] - climate.temperature[2] - 3.0 * 6.5).abs() < 0.01);
+        // Same latitude, but the sea nearby evens out the cold
+        assert!(climate.temperature[1] > climate.temperature[3]);
+    }
+
+    #[test]
+    fn winds_follow_the_circulation_cells() {
+        let normal = |latitude: f32| {
+            let latitude = latitude.to_radians();
+            Vec3::new(latitude.cos(), latitude.sin(), 0.0)
+        };
+        let components = |latitude: f32| {
+            let n = normal(latitude);
+            let (east, north) = east_north(&n);
+            let wind = prevailing_wind(&n);
+            (wind.dot(&east), wind.dot(&north))
+        };
+
+        // Trade winds from the north east, westerlies from the south west
+        let (east, north) = components(15.0);
+        assert!(east < -0.9 && north < -0.4);
+        let (east, north) = components(45.0);
+        assert!(east > 0.9 && north > 0.4);
+        let (east, north) = components(-15.0);
+        assert!(east < -0.9 && north > 0.4);
+        assert!(prevailing_wind(&normal(30.0)).length() < 0.01);
+    }
+
+    #[test]
+    fn mountains_cast_rain_shadows() {
+        // Trade winds blow westwards along the ring, from the sea over a range
+        let n = 36;
+        let (normals, neighbours) = parallel(15.0, n);
+        let mut flat = vec![100.0; n];
+        for e in flat[24..].iter_mut() {
+            *e = -1000.0;
+        }
+        let mut range = flat.clone();
+        range[16] = 4000.0;
+        let config = ClimateConfig::default();
+
+        let with_range = Climate::new(&normals, &range, &neighbours, 300.0, 0.0, &config);
+        let without = Climate::new(&normals, &flat, &neighbours, 300.0, 0.0, &config);
+
+        let windward = 17;
+        let lee = 14;
+        assert!(with_range.precipitation[windward] > without.precipitation[windward]);
+        assert!(with_range.precipitation[lee] < without.precipitation[lee]);
+        assert!(with_range.precipitation[windward] > with_range.precipitation[lee]);
+        // Drier further inland
+        assert!(without.precipitation[23] > without.precipitation[14]);
+    }
+}
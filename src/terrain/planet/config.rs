@@ -0,0 +1,38 @@
+use super::DEFAULT_OCEAN_RATIO;
+
+/// How the corners that plates grow from are picked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Seeding {
+    /// Anywhere, as long as no two seeds share a tile
+    Random,
+    /// Spread evenly, with seeds kept at least a fixed distance apart
+    PoissonDisc,
+}
+
+/// Settings for growing and merging plates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlateConfig {
+    /// Number of plates seeded by `grow_plates`, at least one is
+    pub num_plates: usize,
+    /// Plates with fewer than this share of all tiles are merged into a neighbour
+    pub min_plate_size: f32,
+    /// Exponent skewing which queued tile a plate grows into next towards the earliest
+    /// queued. 1 picks uniformly, higher values give rounder and more even plates. Values
+    /// that are not positive are taken as 1.
+    pub growth_bias: f32,
+    pub seeding: Seeding,
+    /// Share of plates that carry oceanic crust
+    pub ocean_ratio: f32,
+}
+
+impl Default for PlateConfig {
+    fn default() -> PlateConfig {
+        PlateConfig {
+            num_plates: 27,
+            min_plate_size: 1.0 / 30.0,
+            growth_bias: 2.0,
+            seeding: Seeding::Random,
+            ocean_ratio: DEFAULT_OCEAN_RATIO,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use super::{Hydrology, Planet, TileIndex};
+
+/// Settings for weathering the terrain with `Planet::erode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErosionConfig {
+    pub iterations: u32,
+    /// Steepest slope in m/km that loose material rests at, steeper slopes slump
+    pub talus_slope: f32,
+    /// Share of the material above the talus slope that slumps in an iteration
+    pub thermal_rate: f32,
+    /// How fast streams cut into the ground, K in the stream power law K A^m S^n
+    pub erodibility: f32,
+    /// Exponent m of the drained area
+    pub area_exponent: f32,
+    /// Exponent n of the slope
+    pub slope_exponent: f32,
+    /// How much sediment streams carry, as a multiple of what they erode
+    pub transport: f32,
+    /// Share of the sediment beyond what a stream can carry that settles in a tile
+    pub deposition: f32,
+}
+
+impl Default for ErosionConfig {
+    fn default() -> ErosionConfig {
+        ErosionConfig {
+            iterations: 0,
+            talus_slope: 8.0,
+            thermal_rate: 0.5,
+            erodibility: 0.0002,
+            area_exponent: 0.5,
+            slope_exponent: 1.0,
+            transport: 4.0,
+            deposition: 0.5,
+        }
+    }
+}
+
+impl Planet {
+    /// Wears down the terrain over `config.iterations` rounds of thermal and hydraulic
+    /// erosion, changing the elevation of the tiles.
+    ///
+    /// Thermal erosion moves material from every slope steeper than the talus slope to
+    /// the tiles below. Hydraulic erosion lets water follow the drainage: streams cut into
+    /// the ground with the stream power law, carry the sediment downstream and drop what
+    /// they can not carry where they slow down, in lakes and at the coast.
+    ///
+    /// Tectonics sets the elevations afresh, so growing, merging or moving plates undoes
+    /// the erosion. The climate, biomes and drainage are left for `update_surface`.
+    pub fn erode(&mut self, config: &ErosionConfig) {
+        let mut elevations: Vec<f32> =
+            self.tiles.iter().map(|t| self.tile_elevation(t)).collect();
+        let original = elevations.clone();
+        let tile_km = self.tile_size_km();
+
+        for _ in 0..config.iterations {
+            self.slump(&mut elevations, config, tile_km);
+            self.wash(&mut elevations, config, tile_km);
+        }
+
+        for (idx, e) in elevations.iter().enumerate() {
+            self.tile_elevations[idx] += e - original[idx];
+        }
+    }
+
+    /// Thermal erosion: every tile sheds part of its height above the talus slope to each
+    /// lower neighbour, all at once so the order of the tiles does not matter.
+    fn slump(&self, elevations: &mut [f32], config: &ErosionConfig, tile_km: f32) {
+        let talus_drop = config.talus_slope * tile_km;
+        let mut change = vec![0.0; elevations.len()];
+        for (t, neighbours) in self.tile_neighbours.iter().enumerate() {
+            for other in neighbours.iter() {
+                let drop = elevations[t] - elevations[*other as usize];
+                if drop > talus_drop {
+                    // Halved so that a slope never turns over, and shared between neighbours
+                    let moved = config.thermal_rate * (drop - talus_drop) / 2.0 /
+                                neighbours.len() as f32;
+                    change[t] -= moved;
+                    change[*other as usize] += moved;
+                }
+            }
+        }
+        for (e, c) in elevations.iter_mut().zip(change.iter()) {
+            *e += *c;
+        }
+    }
+
+    /// Hydraulic erosion: one pass of water and sediment down the drainage, from the
+    /// sources to the outlets.
+    fn wash(&self, elevations: &mut [f32], config: &ErosionConfig, tile_km: f32) {
+        let hydrology = Hydrology::new(elevations, &self.tile_neighbours, self.sea_level);
+        let downstream = &hydrology.downstream;
+        let drainage = &hydrology.drainage;
+        let mut sediment = vec![0.0f32; elevations.len()];
+
+        for t in upstream_first(downstream) {
+            let t = t as usize;
+            let d = match downstream[t] {
+                Some(d) => d as usize,
+                None => {
+                    // Rivers build up the sea floor at their mouths, the rest is lost offshore
+                    let settled = if elevations[t] < self.sea_level {
+                        sediment[t].min(self.sea_level - elevations[t])
+                    } else {
+                        sediment[t]
+                    };
+                    elevations[t] += settled;
+                    continue;
+                }
+            };
+
+            let drop = elevations[t] - elevations[d];
+            let power = if drop > 0.0 {
+                let area = drainage[t] * tile_km * tile_km;
+                let slope = drop / tile_km;
+                config.erodibility * area.powf(config.area_exponent) *
+                slope.powf(config.slope_exponent)
+            } else {
+                0.0
+            };
+            // Cutting below the next tile would turn the stream around
+            let eroded = power.min(drop / 2.0).max(0.0);
+            elevations[t] -= eroded;
+            sediment[t] += eroded;
+
+            let capacity = config.transport * power;
+            if sediment[t] > capacity {
+                let mut settled = config.deposition * (sediment[t] - capacity);
+                if drop < 0.0 {
+                    // Depressions fill up no higher than their rim
+                    settled = settled.min(-drop);
+                }
+                elevations[t] += settled;
+                sediment[t] -= settled;
+            }
+            sediment[d] += sediment[t];
+        }
+    }
+}
+
+/// Every tile after all tiles draining into it.
+fn upstream_first(downstream: &[Option<TileIndex>]) -> Vec<TileIndex> {
+    let mut inflows = vec![0u32; downstream.len()];
+    for d in downstream.iter() {
+        if let Some(d) = *d {
+            inflows[d as usize] += 1;
+        }
+    }
+    let mut order: Vec<TileIndex> = (0..downstream.len() as TileIndex)
+        .filter(|t| inflows[*t as usize] == 0)
+        .collect();
+    let mut i = 0;
+    while i < order.len() {
+        if let Some(d) = downstream[order[i] as usize] {
+            inflows[d as usize] -= 1;
+            if inflows[d as usize] == 0 {
+                order.push(d);
+            }
+        }
+        i += 1;
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::generator::Generator;
+
+    fn planet() -> Planet {
+        let mut gen = Generator::new(17);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        gen.to_planet()
+    }
+
+    fn steepest_drop(planet: &Planet) -> f32 {
+        let elevations: Vec<f32> =
+            planet.tiles_iter().map(|t| planet.tile_elevation(t)).collect();
+        let mut steepest = 0.0f32;
+        for (t, neighbours) in planet.tile_neighbours.iter().enumerate() {
+            for other in neighbours.iter() {
+                steepest = steepest.max(elevations[t] - elevations[*other as usize]);
+            }
+        }
+        steepest
+    }
+
+    #[test]
+    fn drainage_order_puts_sources_first() {
+        let downstream = [None, Some(0), Some(1), Some(1), Some(3)];
+
+        let order = upstream_first(&downstream);
+
+        assert_eq!(order.len(), 5);
+        for (t, d) in downstream.iter().enumerate() {
+            if let Some(d) = *d {
+                let pos = |x: u32| order.iter().position(|o| *o == x).unwrap();
+                assert!(pos(t as TileIndex) < pos(d));
+            }
+        }
+    }
+
+    #[test]
+    fn erosion_flattens_steep_slopes() {
+        let mut planet = planet();
+        let before = steepest_drop(&planet);
+        let config = ErosionConfig { iterations: 10, ..ErosionConfig::default() };
+
+        planet.erode(&config);
+
+        assert!(steepest_drop(&planet) < before);
+        for e in planet.tile_elevations.iter() {
+            assert!(e.is_finite());
+        }
+    }
+
+    #[test]
+    fn no_iterations_leave_terrain_alone() {
+        let mut planet = planet();
+        let before = planet.tile_elevations.clone();
+
+        planet.erode(&ErosionConfig::default());
+
+        assert_eq!(planet.tile_elevations, before);
+    }
+}
@@ -0,0 +1,425 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use super::{Planet, TileIndex, Vertex, VertexIndex};
+
+// Water a lake tile evaporates, in tiles worth of runoff from the land draining into it
+const EVAPORATION: f32 = 4.0;
+
+/// Where water goes on the surface of a planet.
+///
+/// Everything below sea level is ocean. Water on land runs to the neighbour with the
+/// lowest filled elevation, where depressions are filled up to the point where they spill
+/// over, so that all land eventually drains into the ocean. A planet without ocean drains
+/// into its lowest tile instead.
+#[derive(Clone, Debug, Default)]
+pub struct Hydrology {
+    /// Elevation of every tile with depressions filled to their spill point
+    pub filled: Vec<f32>,
+    /// Tile that each tile drains into, `None` for the ocean and other outlets
+    pub downstream: Vec<Option<TileIndex>>,
+    /// Number of tiles that drain through each tile, counting itself
+    pub drainage: Vec<f32>,
+    /// Lakes and basins in the depressions on land
+    pub water_bodies: Vec<WaterBody>,
+    /// Index into `water_bodies` of the one covering each tile
+    pub water_body: Vec<Option<usize>>,
+}
+
+/// Standing water in a depression on land.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WaterBody {
+    /// Tiles under water, shallowest first
+    pub tiles: Vec<TileIndex>,
+    /// Elevation of the water surface
+    pub surface: f32,
+    /// Elevation where the depression overflows
+    pub spill: f32,
+    /// Tile the lake overflows into, `None` for endorheic basins that lose their water to
+    /// evaporation before it reaches the spill point
+    pub outflow: Option<TileIndex>,
+}
+
+impl WaterBody {
+    pub fn is_endorheic(&self) -> bool {
+        self.outflow.is_none()
+    }
+}
+
+/// A river as the tiles it flows through, from its source to where it reaches an outlet or
+/// joins a larger river.
+#[derive(Clone, Debug, PartialEq)]
+pub struct River {
+    pub tiles: Vec<TileIndex>,
+}
+
+impl Hydrology {
+    /// Floods the tiles from the ocean below `sea_level` upwards, lowest first, the way
+    /// water would rise. Every tile drains into the tile the flood reached it from, which
+    /// makes the directions form a tree rooted at the outlets.
+    pub fn new(elevations: &[f32], neighbours: &[Vec<TileIndex>], sea_level: f32) -> Hydrology {
+        let num_tiles = elevations.len();
+        let mut filled = elevations.to_vec();
+        let mut downstream = vec![None; num_tiles];
+        let mut reached = vec![false; num_tiles];
+        let mut flood = BinaryHeap::new();
+        let mut order = Vec::with_capacity(num_tiles);
+
+        for (t, e) in elevations.iter().enumerate() {
+            if *e < sea_level {
+                reached[t] = true;
+                flood.push(Flood(*e, t as TileIndex));
+            }
+        }
+        if flood.is_empty() {
+            let lowest = elevations.iter()
+                .enumerate()
+                .min_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(Ordering::Equal));
+            if let Some((t, e)) = lowest {
+                reached[t] = true;
+                flood.push(Flood(*e, t as TileIndex));
+            }
+        }
+
+        while let Some(Flood(level, tile)) = flood.pop() {
+            order.push(tile);
+            for other in neighbours[tile as usize].iter() {
+                let o = *other as usize;
+                if !reached[o] {
+                    reached[o] = true;
+                    filled[o] = filled[o].max(level);
+                    downstream[o] = Some(tile);
+                    flood.push(Flood(filled[o], *other));
+                }
+            }
+        }
+
+        // Tiles come after the tile they drain into, so walk backwards to gather drainage
+        let mut drainage = vec![1.0; num_tiles];
+        for t in order.iter().rev() {
+            if let Some(d) = downstream[*t as usize] {
+                drainage[d as usize] += drainage[*t as usize];
+            }
+        }
+
+        let mut hydrology = Hydrology {
+            filled,
+            downstream,
+            drainage,
+            water_bodies: Vec::new(),
+            water_body: vec![None; num_tiles],
+        };
+        hydrology.find_water_bodies(elevations, neighbours, sea_level);
+        hydrology
+    }
+
+    /// Groups the filled tiles on land into depressions with a common spill point. A
+    /// depression fills up to it only if the land draining into it sheds more water than
+    /// its surface evaporates, otherwise the water stops where the two balance.
+    fn find_water_bodies(&mut self,
+                         elevations: &[f32],
+                         neighbours: &[Vec<TileIndex>],
+                         sea_level: f32) {
+        let num_tiles = elevations.len();
+        let mut depression = vec![None; num_tiles];
+        let mut depressions: Vec<Vec<TileIndex>> = Vec::new();
+        {
+            let filled = &self.filled;
+            let flooded = |t: usize| elevations[t] >= sea_level && filled[t] > elevations[t];
+            for start in 0..num_tiles {
+                if depression[start].is_some() || !flooded(start) {
+                    continue;
+                }
+                let mut tiles = vec![start as TileIndex];
+                depression[start] = Some(depressions.len());
+                let mut i = 0;
+                while i < tiles.len() {
+                    for other in neighbours[tiles[i] as usize].iter() {
+                        let o = *other as usize;
+                        if depression[o].is_none() && flooded(o) && filled[o] == filled[start] {
+                            depression[o] = Some(depressions.len());
+                            tiles.push(*other);
+                        }
+                    }
+                    i += 1;
+                }
+                depressions.push(tiles);
+            }
+        }
+
+        for (d, mut tiles) in depressions.into_iter().enumerate() {
+            // Water enters and leaves through the tiles draining out of the depression
+            let mut inflow = 0.0;
+            let mut outflow = None;
+            for t in tiles.iter() {
+                if let Some(next) = self.downstream[*t as usize] {
+                    if depression[next as usize] != Some(d) {
+                        inflow += self.drainage[*t as usize];
+                        outflow = Some(next);
+                    }
+                }
+            }
+            let spill = self.filled[tiles[0] as usize];
+
+            tiles.sort_by(|a, b| {
+                let (ea, eb) = (elevations[*a as usize], elevations[*b as usize]);
+                ea.partial_cmp(&eb).unwrap_or(Ordering::Equal)
+            });
+            let surface = if inflow > tiles.len() as f32 * EVAPORATION {
+                spill
+            } else {
+                outflow = None;
+                let num_wet = ((inflow / EVAPORATION).ceil() as usize).max(1).min(tiles.len());
+                tiles.truncate(num_wet);
+                elevations[tiles[num_wet - 1] as usize]
+            };
+            tiles.reverse();
+
+            for t in tiles.iter() {
+                self.water_body[*t as usize] = Some(self.water_bodies.len());
+            }
+            self.water_bodies.push(WaterBody {
+                tiles,
+                surface,
+                spill,
+                outflow,
+            });
+        }
+    }
+
+    /// Rivers through every tile draining at least `min_drainage` tiles. Each starts
+    /// where the drainage first reaches the limit, and the first river to reach a tile
+    /// continues through it while later ones end there.
+    pub fn rivers(&self, min_drainage: f32) -> Vec<River> {
+        let num_tiles = self.drainage.len();
+        let mut fed = vec![false; num_tiles];
+        for t in 0..num_tiles {
+            if let Some(d) = self.downstream[t] {
+                if self.drainage[t] >= min_drainage {
+                    fed[d as usize] = true;
+                }
+            }
+        }
+
+        let mut visited = vec![false; num_tiles];
+        let mut rivers = Vec::new();
+        for source in 0..num_tiles {
+            if fed[source] || self.drainage[source] < min_drainage ||
+               self.downstream[source].is_none() {
+                continue;
+            }
+            let mut tiles = vec![source as TileIndex];
+            visited[source] = true;
+            let mut tile = source;
+            while let Some(next) = self.downstream[tile] {
+                tiles.push(next);
+                if visited[next as usize] {
+                    break;
+                }
+                visited[next as usize] = true;
+                tile = next as usize;
+            }
+            rivers.push(River { tiles });
+        }
+        rivers
+    }
+}
+
+impl Planet {
+    pub fn hydrology(&self) -> &Hydrology {
+        &self.hydrology
+    }
+
+    pub fn water_bodies(&self) -> &[WaterBody] {
+        &self.hydrology.water_bodies
+    }
+
+    /// The lake or basin covering a tile, if any.
+    pub fn water_body_at(&self, tile: TileIndex) -> Option<&WaterBody> {
+        self.hydrology.water_body[tile as usize].map(|w| &self.hydrology.water_bodies[w])
+    }
+
+    /// Points along a river, following the tile borders from corner to corner. The river
+    /// crosses into each tile at a corner of the border it enters by, and runs the shorter
+    /// way around the tile to a corner of the border it leaves by.
+    pub fn river_path(&self, river: &River) -> Vec<Vertex> {
+        let mut corners: Vec<VertexIndex> = Vec::new();
+        for pair in river.tiles.windows(2) {
+            let tile = &self.tiles[pair[0] as usize];
+            let crossing = tile.borders
+                .iter()
+                .map(|b| &self.borders[*b as usize])
+                .find(|b| b.other_tile(pair[0]) == Some(pair[1]));
+            if let Some(border) = crossing {
+                let (a, b) = border.vertices;
+                match corners.last().cloned() {
+                    None => corners.push(a),
+                    Some(from) => {
+                        let ring: Vec<VertexIndex> = tile.vertices_iter().cloned().collect();
+                        corners.extend(walk_around(&ring, from, a, b));
+                    }
+                }
+            }
+        }
+        corners.iter().map(|c| &self.vertices[*c as usize] * self.scale).collect()
+    }
+
+    /// Recomputes where water flows after the elevations have changed.
+    pub(super) fn update_hydrology(&mut self) {
+        let elevations: Vec<f32> = self.tiles.iter().map(|t| self.tile_elevation(t)).collect();
+        self.hydrology = Hydrology::new(&elevations, &self.tile_neighbours, self.sea_level);
+    }
+}
+
+/// Corners after `from` on the shorter way around a tile to `a` or `b`, whichever comes
+/// first.
+fn walk_around(ring: &[VertexIndex],
+               from: VertexIndex,
+               a: VertexIndex,
+               b: VertexIndex)
+               -> Vec<VertexIndex> {
+    let n = ring.len();
+    let start = match ring.iter().position(|c| *c == from) {
+        Some(start) => start,
+        None => return vec![a],
+    };
+    let forward: Vec<VertexIndex> = (0..n).map(|k| ring[(start + k) % n]).collect();
+    let backward: Vec<VertexIndex> = (0..n).map(|k| ring[(start + n - k) % n]).collect();
+    let reach = |way: &[VertexIndex]| way.iter().position(|c| *c == a || *c == b).unwrap_or(0);
+    let (ahead, behind) = (reach(&forward), reach(&backward));
+    if ahead <= behind {
+        forward[1..ahead + 1].to_vec()
+    } else {
+        backward[1..behind + 1].to_vec()
+    }
+}
+
+/// Tile waiting to be flooded at a level, ordered so the lowest is flooded first.
+#[derive(PartialEq)]
+struct Flood(f32, TileIndex);
+
+impl Eq for Flood {}
+
+impl Ord for Flood {
+    fn cmp(&self, other: &Flood) -> Ordering {
+        other.0
+            .partial_cmp(&self.0)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.1.cmp(&self.1))
+    }
+}
+
+impl PartialOrd for Flood {
+    fn partial_cmp(&self, other: &Flood) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::generator::Generator;
+
+    // Tiles in a row, each the neighbour of the next
+    fn row(elevations: &[f32]) -> Hydrology {
+        let n = elevations.len() as TileIndex;
+        let neighbours: Vec<Vec<TileIndex>> = (0..n)
+            .map(|t| (t.saturating_sub(1)..(t + 2).min(n)).filter(|o| *o != t).collect())
+            .collect();
+        Hydrology::new(elevations, &neighbours, 0.0)
+    }
+
+    // From the ocean at 0, over a hill at 3, down into a pit at 5
+    fn valley() -> Hydrology {
+        row(&[-10.0, 5.0, 20.0, 50.0, 30.0, 10.0, 25.0])
+    }
+
+    #[test]
+    fn depressions_fill_to_spill_point() {
+        let hydrology = valley();
+
+        assert_eq!(hydrology.filled, vec![-10.0, 5.0, 20.0, 50.0, 50.0, 50.0, 50.0]);
+        assert_eq!(hydrology.downstream,
+                   vec![None, Some(0), Some(1), Some(2), Some(3), Some(4), Some(5)]);
+        assert_eq!(hydrology.drainage, vec![7.0, 6.0, 5.0, 4.0, 3.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn small_catchment_leaves_endorheic_basin() {
+        let hydrology = valley();
+
+        assert_eq!(hydrology.water_bodies,
+                   vec![WaterBody {
+                            tiles: vec![5],
+                            surface: 10.0,
+                            spill: 50.0,
+                            outflow: None,
+                        }]);
+        assert_eq!(hydrology.water_body[5], Some(0));
+        assert_eq!(hydrology.water_body[4], None);
+    }
+
+    #[test]
+    fn large_catchment_fills_lake_to_spill_point() {
+        let hydrology = row(&[-10.0, 5.0, 20.0, 15.0, 30.0, 31.0, 32.0, 33.0, 34.0, 35.0]);
+
+        assert_eq!(hydrology.water_bodies,
+                   vec![WaterBody {
+                            tiles: vec![3],
+                            surface: 20.0,
+                            spill: 20.0,
+                            outflow: Some(2),
+                        }]);
+        assert!(!hydrology.water_bodies[0].is_endorheic());
+    }
+
+    #[test]
+    fn rivers_start_at_drainage_limit() {
+        let hydrology = valley();
+
+        assert_eq!(hydrology.rivers(4.0), vec![River { tiles: vec![3, 2, 1, 0] }]);
+    }
+
+    #[test]
+    fn all_land_drains_to_an_outlet() {
+        let mut gen = Generator::new(5);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let mut planet = gen.to_planet();
+        planet.update_surface();
+        let hydrology = planet.hydrology();
+
+        let mut outflow = 0.0;
+        for t in 0..planet.num_tiles() {
+            let mut tile = t;
+            let mut steps = 0;
+            while let Some(next) = hydrology.downstream[tile] {
+                assert!(hydrology.filled[next as usize] <= hydrology.filled[tile]);
+                tile = next as usize;
+                steps += 1;
+                assert!(steps < planet.num_tiles(), "Flow from {} never ends", t);
+            }
+            if tile == t {
+                outflow += hydrology.drainage[t];
+            }
+        }
+        assert_eq!(outflow, planet.num_tiles() as f32);
+
+        for river in hydrology.rivers(10.0) {
+            for pair in river.tiles.windows(2) {
+                assert_eq!(hydrology.downstream[pair[0] as usize], Some(pair[1]));
+            }
+            // Every step of the path runs along a tile border
+            let path = planet.river_path(&river);
+            assert!(!path.is_empty());
+            for step in path.windows(2) {
+                assert!(planet.borders_iter().any(|b| {
+                    let a = &planet.vertices[b.vertices.0 as usize] * planet.scale;
+                    let c = &planet.vertices[b.vertices.1 as usize] * planet.scale;
+                    (a == step[0] && c == step[1]) || (a == step[1] && c == step[0])
+                }));
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use math::normalize;
+
+use std::cmp::Ordering;
+use std::f32;
+
+use super::{Vertex, TileIndex};
+
+/// Balanced kd-tree over tile midpoints projected onto the unit sphere, answering which
+/// midpoint is nearest to a direction in logarithmic time.
+///
+/// On the sphere the nearest midpoint by straight line distance is also the nearest by
+/// angle, so no spherical geometry is needed. The tree is stored implicitly: the median
+/// of every range is its root, with the halves on either side as subtrees.
+#[derive(Clone, Debug)]
+pub struct SpatialIndex {
+    points: Vec<(Vertex, TileIndex)>,
+}
+
+impl SpatialIndex {
+    pub fn new(midpoints: Vec<Vertex>) -> SpatialIndex {
+        let mut points: Vec<(Vertex, TileIndex)> = midpoints.into_iter()
+            .enumerate()
+            .map(|(i, p)| (normalize(p), i as TileIndex))
+            .collect();
+        build(&mut points, 0);
+        SpatialIndex { points }
+    }
+
+    /// Tile with the nearest midpoint, or `None` for an empty index.
+    pub fn nearest(&self, direction: &Vertex) -> Option<TileIndex> {
+        let target = normalize(direction.clone());
+        let mut best = (None, f32::MAX);
+        self.search(0, self.points.len(), 0, &target, &mut best);
+        best.0
+    }
+
+    fn search(&self,
+              lo: usize,
+              hi: usize,
+              depth: u32,
+              target: &Vertex,
+              best: &mut (Option<TileIndex>, f32)) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let (ref point, tile) = self.points[mid];
+
+        let d = (target - point).length_squared();
+        if d < best.1 {
+            *best = (Some(tile), d);
+        }
+
+        let axis = depth % 3;
+        let delta = target[axis] - point[axis];
+        let (near, far) = if delta < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        self.search(near.0, near.1, depth + 1, target, best);
+        if delta * delta < best.1 {
+            self.search(far.0, far.1, depth + 1, target, best);
+        }
+    }
+}
+
+fn build(points: &mut [(Vertex, TileIndex)], depth: u32) {
+    if points.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    points.sort_by(|a, b| a.0[axis].partial_cmp(&b.0[axis]).unwrap_or(Ordering::Equal));
+    let mid = points.len() / 2;
+    let (lower, upper) = points.split_at_mut(mid);
+    build(lower, depth + 1);
+    build(&mut upper[1..], depth + 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::{DotProduct, TerrainRng};
+    use rand::Rng;
+
+    fn random_direction(rng: &mut TerrainRng) -> Vertex {
+        Vertex::new(rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0))
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let mut rng = TerrainRng::new(3);
+        let midpoints: Vec<Vertex> = (0..500).map(|_| random_direction(&mut rng)).collect();
+        let index = SpatialIndex::new(midpoints.clone());
+
+        for _ in 0..1000 {
+            let direction = normalize(random_direction(&mut rng));
+            let expected = midpoints.iter()
+                .map(|m| normalize(m.clone()).dot(&direction))
+                .enumerate()
+                .fold((0, f32::MIN), |a, (i, d)| if d > a.1 { (i, d) } else { a });
+
+            assert_eq!(index.nearest(&direction), Some(expected.0 as TileIndex));
+        }
+    }
+
+    #[test]
+    fn empty_index_has_no_nearest() {
+        let index = SpatialIndex::new(Vec::new());
+
+        assert_eq!(index.nearest(&Vertex::new(0.0, 1.0, 0.0)), None);
+    }
+}
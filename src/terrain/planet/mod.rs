@@ -1,23 +1,44 @@
+mod biome;
 mod border;
+mod climate;
+mod config;
+mod erosion;
+mod hydrology;
+mod index;
+mod ocean;
+mod persist;
 mod plate;
+mod queue;
+mod simulation;
+mod tectonics;
 mod tile;
 
-use math::{Vec3, DotProduct};
-use math::{normalize, sorted_pair};
+use math::{Vec3, DotProduct, TerrainRng};
+use math::{distance, normalize, sorted_pair};
 
 use std::f32;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::mem;
 use std::slice::Iter;
 
-use rand::thread_rng;
 use rand::Rng;
 use rand::distributions::{IndependentSample, Range};
 
 use noise::{NoiseModule, RidgedMulti};
 
-pub use self::plate::Plate;
-pub use self::border::Border;
+pub use self::plate::{Plate, PlateMerge, CrustType, DEFAULT_OCEAN_RATIO};
+pub use self::biome::{Biome, BiomeRule, BiomeRules, BIOMES};
+pub use self::border::{Border, Boundary, BoundaryType};
+pub use self::climate::{Climate, ClimateConfig};
+pub use self::config::{PlateConfig, Seeding};
+pub use self::erosion::ErosionConfig;
+pub use self::hydrology::{Hydrology, River, WaterBody};
 pub use self::tile::Tile;
+pub use self::simulation::{Simulation, Snapshot, StepReport};
+
+use self::index::SpatialIndex;
+use self::queue::GrowthQueue;
 
 pub type Vertex = Vec3<f32>;
 pub type VertexIndex = u32;
@@ -28,23 +49,50 @@ pub type PlateIndex = u32;
 pub struct Planet {
     vertices: Vec<Vertex>,
     tiles: Vec<Tile>,
-    #[allow(dead_code)]
     borders: Vec<Border>,
     elevations: Vec<f32>,
+    tile_elevations: Vec<f32>,
+    sea_level: f32,
     vertex_to_tiles: Vec<Vec<TileIndex>>,
     tile_neighbours: Vec<Vec<TileIndex>>,
+    index: SpatialIndex,
+    hydrology: Hydrology,
+    climate: Climate,
+    climate_config: ClimateConfig,
+    biomes: Vec<Option<Biome>>,
+    biome_rules: BiomeRules,
     num_corners: usize,
     num_tiles: usize,
     num_plates: usize,
     plates: Vec<Plate>,
     scale: f32,
+    config: PlateConfig,
+    rng: TerrainRng,
+}
+
+/// What a planet is built from, whether generated or loaded. Everything else is derived.
+struct PlanetParts {
+    vertices: Vec<Vertex>,
+    tiles: Vec<Tile>,
+    borders: Vec<Border>,
+    elevations: Vec<f32>,
+    plates: Vec<Plate>,
+    scale: f32,
+    config: PlateConfig,
+    rng: TerrainRng,
 }
 
 impl Planet {
-    pub fn new(vertices: Vec<Vertex>, borders: Vec<Vec<VertexIndex>>) -> Planet {
+    pub fn new(vertices: Vec<Vertex>,
+               borders: Vec<Vec<VertexIndex>>,
+               seed: u64,
+               config: PlateConfig)
+               -> Planet {
+        let mut rng = TerrainRng::new(seed);
         let num_tiles = borders.len();
         let num_corners = vertices.len() - num_tiles;
-        let mut borders_map = HashMap::<(VertexIndex, VertexIndex), Vec<TileIndex>>::new();
+        // Ordered, so that border indices (and everything built from them) are reproducible
+        let mut borders_map = BTreeMap::<(VertexIndex, VertexIndex), Vec<TileIndex>>::new();
 
         let mut tiles = Vec::with_capacity(borders.len());
         let mut i = num_corners;
@@ -77,6 +125,46 @@ impl Planet {
         }
         drop(borders_map);
 
+        let fbm = RidgedMulti::new();
+        //fbm = fbm.set_frequency(7.5);
+
+        // Sample the noise around a seed dependent offset, so each seed gets its own terrain
+        let noise_range = Range::new(-100.0, 100.0);
+        let offset = [noise_range.ind_sample(&mut rng),
+                      noise_range.ind_sample(&mut rng),
+                      noise_range.ind_sample(&mut rng)];
+
+        let mut elevations = Vec::with_capacity(num_corners);
+        for vert in vertices[0..num_corners].iter() {
+            let v = [vert.x + offset[0], vert.y + offset[1], vert.z + offset[2]];
+            let e = fbm.get(v) * 100.0;
+            elevations.push(e);
+        }
+
+        let mut planet = Planet::from_parts(PlanetParts {
+            vertices,
+            tiles,
+            borders: borders_vec,
+            elevations,
+            plates: Vec::new(),
+            scale: 10.0,
+            config,
+            rng,
+        });
+
+        planet.grow_plates();
+
+        planet
+    }
+
+    /// Assembles a planet from its stored parts, deriving the lookup tables.
+    fn from_parts(parts: PlanetParts) -> Planet {
+        let PlanetParts {
+            vertices, tiles, borders: borders_vec, elevations, plates, scale, config, rng
+        } = parts;
+        let num_tiles = tiles.len();
+        let num_corners = vertices.len() - num_tiles;
+
         let mut vertex_tiles = vec![vec!(); num_corners];
 
         for (idx, tile) in tiles.iter().enumerate() {
@@ -117,32 +205,35 @@ impl Planet {
         }
 */
 
-        let fbm = RidgedMulti::new();
-        //fbm = fbm.set_frequency(7.5);
-
-        let mut elevations = Vec::with_capacity(num_corners);
-        for vert in vertices[0..num_corners].iter() {
-            let v = [vert.x, vert.y, vert.z];
-            let e = fbm.get(v) * 100.0;
-            elevations.push(e);
-        }
+        let index = SpatialIndex::new(tiles.iter()
+            .map(|t| vertices[t.midpoint as usize].clone())
+            .collect());
 
         let mut planet = Planet {
             vertices: vertices,
             tiles: tiles,
             borders: borders_vec,
             elevations: elevations,
+            tile_elevations: vec![0.0; num_tiles],
+            sea_level: 0.0,
             vertex_to_tiles: vertex_tiles,
             tile_neighbours: tile_neighbours,
+            index: index,
+            hydrology: Hydrology::default(),
+            climate: Climate::default(),
+            climate_config: ClimateConfig::default(),
+            biomes: Vec::new(),
+            biome_rules: BiomeRules::default(),
             num_corners: num_corners,
             num_tiles: num_tiles,
-            num_plates: 0,
-            plates: Vec::new(),
-            scale: 10.0,
+            num_plates: plates.len(),
+            plates: plates,
+            scale: scale,
+            config: config,
+            rng: rng,
         };
-
-        planet.grow_plates();
-
+        planet.classify_boundaries();
+        planet.uplift();
         planet
     }
 
@@ -154,6 +245,27 @@ impl Planet {
         self.num_tiles
     }
 
+    pub fn num_plates(&self) -> usize {
+        self.num_plates
+    }
+
+    pub fn plate_config(&self) -> &PlateConfig {
+        &self.config
+    }
+
+    /// Sets how the next `grow_plates` and `merge_plates` shape the plates.
+    pub fn set_plate_config(&mut self, config: PlateConfig) {
+        self.config = config;
+    }
+
+    pub fn borders_iter(&self) -> Iter<Border> {
+        self.borders.iter()
+    }
+
+    pub fn border(&self, index: BorderIndex) -> &Border {
+        &self.borders[index as usize]
+    }
+
     pub fn tile_normal(&self, tile: &Tile) -> Vertex {
         normalize(self.vertices[tile.midpoint as usize].clone())
     }
@@ -175,9 +287,80 @@ impl Planet {
             elevation += self.elevations[*vi as usize];
             n += 1;
         }
-        elevation / n as f32 + self.plates[tile.plate_id as usize - 1].base_elevation
+        elevation / n as f32 + self.tile_elevations[self.tile_index(tile)]
+    }
+
+    fn tile_index(&self, tile: &Tile) -> usize {
+        tile.midpoint as usize - self.num_corners
+    }
+
+    /// Recomputes the climate, biomes and where water flows from the current elevations.
+    ///
+    /// Growing, merging and moving plates leave these as they were, so a planet can go
+    /// through several of them and pay for the surface only once afterwards.
+    pub fn update_surface(&mut self) {
+        self.update_climate();
+        self.update_hydrology();
     }
 
+    pub fn tile(&self, index: TileIndex) -> &Tile {
+        &self.tiles[index as usize]
+    }
+
+    /// The tile under `direction`, taken as the one with the nearest midpoint.
+    pub fn tile_at(&self, direction: &Vertex) -> TileIndex {
+        self.index.nearest(direction).expect("Planet without tiles")
+    }
+
+    /// Elevation of the sea surface. Tiles below it are ocean.
+    pub fn sea_level(&self) -> f32 {
+        self.sea_level
+    }
+
+    /// Moves the sea surface. Coastlines follow at once, the climate, biomes and drainage
+    /// on the next `update_surface`.
+    pub fn set_sea_level(&mut self, sea_level: f32) {
+        self.sea_level = sea_level;
+    }
+
+    /// Changes the sea level, climate settings and biome rules and recomputes the surface
+    /// for them.
+    pub fn set_surface(&mut self, sea_level: f32, climate: ClimateConfig, rules: BiomeRules) {
+        self.sea_level = sea_level;
+        self.climate_config = climate;
+        self.biome_rules = rules;
+        self.update_surface();
+    }
+
+    pub fn is_ocean(&self, tile: &Tile) -> bool {
+        self.tile_elevation(tile) < self.sea_level
+    }
+
+    /// Share of the tiles above sea level.
+    pub fn land_fraction(&self) -> f32 {
+        let land = self.tiles.iter().filter(|t| !self.is_ocean(t)).count();
+        land as f32 / self.num_tiles as f32
+    }
+
+    /// Sea level that leaves the share `land_fraction` of the tiles above it, halfway
+    /// between the highest ocean tile and the lowest land tile. Tiles are close enough in
+    /// size that this is about the same share of the surface.
+    pub fn sea_level_for_land_fraction(&self, land_fraction: f32) -> f32 {
+        let mut elevations: Vec<f32> = self.tiles.iter().map(|t| self.tile_elevation(t)).collect();
+        elevations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let land_fraction = land_fraction.clamp(0.0, 1.0);
+        let num_ocean = ((1.0 - land_fraction) * elevations.len() as f32).round() as usize;
+        if num_ocean == 0 {
+            elevations[0]
+        } else if num_ocean == elevations.len() {
+            // Just above the highest peak
+            elevations[num_ocean - 1] + 1.0
+        } else {
+            (elevations[num_ocean - 1] + elevations[num_ocean]) / 2.0
+        }
+    }
+
+    /// Depth of the deepest tile below sea level and height of the highest tile above it.
     pub fn get_elevation_scale(&self) -> (f32, f32) {
         let mut min_elevation = f32::MAX;
         let mut max_elevation = f32::MIN;
@@ -192,28 +375,58 @@ impl Planet {
             }
         }
 
-        // [min_elevation, 0] -> [-1, 0]  y = x / -min_elevation
-        // [0, max_elevation] -> [0, 1]   y = x / max_elevation
+        // [min_elevation, sea_level] -> [-1, 0]  y = x / (sea_level - min_elevation)
+        // [sea_level, max_elevation] -> [0, 1]   y = x / (max_elevation - sea_level)
 
-        (-min_elevation, max_elevation)
+        (self.sea_level - min_elevation, max_elevation - self.sea_level)
+    }
+
+    /// Recomputes the boundary of every border from the plates and movement of its tiles.
+    fn classify_boundaries(&mut self) {
+        for border in self.borders.iter_mut() {
+            let a = &self.tiles[border.tiles.0 as usize];
+            let b = &self.tiles[border.tiles.1 as usize];
+            border.boundary = if a.plate_id == 0 || b.plate_id == 0 || a.plate_id == b.plate_id {
+                None
+            } else {
+                Some(Boundary::from_movement(&self.vertices[a.midpoint as usize],
+                                             &a.movement_vector,
+                                             &self.vertices[b.midpoint as usize],
+                                             &b.movement_vector))
+            };
+        }
     }
 
-    fn initialize_plates(&mut self, num_plates: usize) -> Vec<(TileIndex, u32)> {
+    fn initialize_plates(&mut self, num_plates: usize) -> GrowthQueue<(TileIndex, PlateIndex)> {
         // Clear previous plate assignments
         for tile in self.tiles.iter_mut() {
             tile.plate_id = 0;
         }
 
         let mut plates: Vec<Plate> = Vec::new();
-        let mut rng = thread_rng();
+        let mut seeds: Vec<Vertex> = Vec::new();
         let between = Range::new(0, self.num_corners);
+        let mut separation = match self.config.seeding {
+            Seeding::Random => 0.0,
+            Seeding::PoissonDisc => poisson_disc_separation(num_plates),
+        };
 
         let mut failed_count = 0;
 
-        let mut assign_queue = Vec::new();
+        let mut assign_queue = GrowthQueue::new();
 
         while plates.len() < num_plates && failed_count < 10000 {
-            let corner = &self.vertex_to_tiles[between.ind_sample(&mut rng)].clone();
+            let corner_idx = between.ind_sample(&mut self.rng);
+            let position = normalize(self.vertices[corner_idx].clone());
+            if seeds.iter().any(|s| distance(s, &position) < separation) {
+                failed_count += 1;
+                // Random darts may leave no room for the last seeds, so let them closer
+                if failed_count % 1000 == 0 {
+                    separation *= 0.9;
+                }
+                continue;
+            }
+            let corner = &self.vertex_to_tiles[corner_idx].clone();
             let mut adjacent_to_existing_plate = false;
             for tile_idx in corner.iter() {
                 if self.tiles[*tile_idx as usize].plate_id > 0 {
@@ -228,7 +441,8 @@ impl Planet {
 
             failed_count = 0;
 
-            let mut plate = Plate::new(1 + plates.len() as u32);
+            let mut plate =
+                Plate::new(1 + plates.len() as u32, self.config.ocean_ratio, &mut self.rng);
 
             for tile_idx in corner.iter() {
                 plate.add_tile(*tile_idx, &self.tiles[*tile_idx as usize].borders);
@@ -247,6 +461,7 @@ impl Planet {
             }
 
             plates.push(plate);
+            seeds.push(position);
         }
 
         self.num_plates = plates.len();
@@ -278,24 +493,63 @@ impl Planet {
         &plate.axis_of_rotation.cross(&perpendicular) * plate.angular_velocity
     }
 
-    fn assign_plates(&mut self) -> &Self {
+    /// Moves every tile to the plate given by `plate_ids`, dropping plates left without
+    /// tiles, and brings plate ids, tile lists, borders, movement vectors and boundaries up
+    /// to date. The elevations are left to the caller.
+    fn reassign_tiles(&mut self, plate_ids: &[PlateIndex]) {
+        let mut plates: Vec<Plate> = self.plates.drain(..).collect();
+        for plate in plates.iter_mut() {
+            plate.tiles.clear();
+            plate.borders.clear();
+        }
+        for (idx, id) in plate_ids.iter().enumerate() {
+            plates[*id as usize - 1].tiles.push(idx as TileIndex);
+        }
+        for (idx, border) in self.borders.iter().enumerate() {
+            let a = plate_ids[border.tiles.0 as usize];
+            let b = plate_ids[border.tiles.1 as usize];
+            if a != b {
+                plates[a as usize - 1].borders.insert(idx as BorderIndex);
+                plates[b as usize - 1].borders.insert(idx as BorderIndex);
+            }
+        }
 
-        for plate in self.plates.iter() {
-            for tile_idx in plate.tiles.iter() {
-                let tile = &mut self.tiles[*tile_idx as usize];
-                tile.plate_id = plate.id;
+        // Tiles look up their plate by id, so keep ids contiguous
+        plates.retain(|p| !p.tiles.is_empty());
+        for (idx, plate) in plates.iter_mut().enumerate() {
+            plate.id = idx as PlateIndex + 1;
+        }
+        self.num_plates = plates.len();
+        self.plates = plates;
+
+        for p in 0..self.plates.len() {
+            for t in self.plates[p].tiles.iter() {
+                let movement_vector =
+                    self.calculate_movement_vector(&self.plates[p], &self.tiles[*t as usize]);
+                let tile = &mut self.tiles[*t as usize];
+                tile.plate_id = p as PlateIndex + 1;
+                tile.movement_vector = movement_vector;
             }
         }
-        self
+
+        self.classify_boundaries();
     }
 
     pub fn grow_plates(&mut self) {
-        let mut assign_queue = self.initialize_plates(27);
+        let num_plates = self.config.num_plates.max(1);
+        let mut assign_queue = self.initialize_plates(num_plates);
+        let growth_bias = if self.config.growth_bias > 0.0 {
+            self.config.growth_bias
+        } else {
+            1.0
+        };
 
         while !assign_queue.is_empty() {
-            let mut rng = thread_rng();
-            let idx = (rng.next_f32().powf(2.0) * assign_queue.len() as f32).floor() as usize;
-            let (tile_idx, plate_id) = assign_queue.remove(idx);
+            let pick = self.rng.next_f32().powf(growth_bias);
+            // A tiny bias can round the pick up to 1
+            let idx = ((pick * assign_queue.len() as f32).floor() as usize)
+                .min(assign_queue.len() - 1);
+            let (tile_idx, plate_id) = assign_queue.remove(idx).unwrap();
 
             if self.tiles[tile_idx as usize].plate_id == 0 {
                 self.assign_plate_id_to_tile(plate_id, tile_idx);
@@ -311,56 +565,373 @@ impl Planet {
 
         }
 
+        self.classify_boundaries();
+        self.age_crust();
+        self.uplift();
     }
 
-    pub fn merge_plates(&mut self) {
-        let min_plate_size = self.num_tiles() / 30;
-        println!("Minimum plate size is {}", min_plate_size);
-        let mut plates = self.plates.clone();
-
-        loop {
-            plates.sort_by(|a, b| a.tiles.len().cmp(&b.tiles.len()));
-            if plates[0].tiles.len() >= min_plate_size {
-                println!("All plates big enough");
+    /// Merges every plate smaller than the configured minimum size into its smallest
+    /// neighbour, and returns the merges in the order they were made. A small plate
+    /// without neighbours, which only happens when nothing else is left, stays as it is.
+    ///
+    /// Plates are renumbered afterwards to keep ids contiguous, while the returned merges
+    /// refer to the ids from before.
+    pub fn merge_plates(&mut self) -> Vec<PlateMerge> {
+        let min_plate_size = (self.num_tiles as f32 * self.config.min_plate_size) as usize;
+        let mut sizes: Vec<usize> = self.plates.iter().map(|p| p.tiles.len()).collect();
+        // Each plate points at the plate it was merged into, or at itself while it remains
+        let mut merged_into: Vec<usize> = (0..sizes.len()).collect();
+        let mut smallest: BinaryHeap<_> = sizes.iter()
+            .enumerate()
+            .map(|(idx, size)| Reverse((*size, idx)))
+            .collect();
+        let mut members: Vec<Vec<TileIndex>> =
+            self.plates.iter().map(|p| p.tiles.clone()).collect();
+        let mut merges = Vec::new();
+
+        while let Some(Reverse((size, idx))) = smallest.pop() {
+            // Merged away, or grown since it was queued
+            if merged_into[idx] != idx || sizes[idx] != size {
+                continue;
+            }
+            if size >= min_plate_size {
                 break;
             }
 
-            // Find neighbouring plates
+            // Merge with the smallest neighbouring plate. Being at least as big as this
+            // one, it at least doubles in size, so no tile is visited more than a
+            // logarithmic number of times.
+            let mut neighbour: Option<usize> = None;
+            for own_tile in members[idx].iter() {
+                for other_tile in self.tile_neighbours[*own_tile as usize].iter() {
+                    let plate_id = self.tiles[*other_tile as usize].plate_id;
+                    if plate_id == 0 {
+                        continue;
+                    }
+                    let other = find_merged(&mut merged_into, plate_id as usize - 1);
+                    let smaller = |n: usize| sizes[other] < sizes[n];
+                    if other != idx && neighbour.is_none_or(smaller) {
+                        neighbour = Some(other);
+                    }
+                }
+            }
 
-            let mut neighbouring_plates = Vec::new();
-            {
+            let other = match neighbour {
+                Some(other) => other,
+                None => continue,
+            };
+            merges.push(PlateMerge {
+                absorbed: self.plates[idx].id,
+                absorbed_tiles: sizes[idx],
+                into: self.plates[other].id,
+                into_tiles: sizes[other],
+            });
+            let mut tiles = mem::take(&mut members[idx]);
+            members[other].append(&mut tiles);
+            sizes[other] += sizes[idx];
+            sizes[idx] = 0;
+            merged_into[idx] = other;
+            // The absorbed tiles have not moved with the plate, so it starts moving afresh
+            self.plates[other].frame.clear();
+            self.plates[other].rotation = 0.0;
+            smallest.push(Reverse((sizes[other], other)));
+        }
 
-                let plate = &plates[0];
-                'lp: for i in 1..plates.len() {
-                    let other_plate = &plates[i];
+        let mut plate_ids: Vec<PlateIndex> = self.tiles.iter().map(|t| t.plate_id).collect();
+        for (idx, tiles) in members.iter().enumerate() {
+            for t in tiles.iter() {
+                plate_ids[*t as usize] = idx as PlateIndex + 1;
+            }
+        }
+        self.reassign_tiles(&plate_ids);
+        self.age_crust();
+        self.uplift();
 
-                    for own_tile in plate.tiles.iter() {
-                        for other_tile in self.tile_neighbours[*own_tile as usize].iter() {
-                            if other_plate.tiles.contains(&other_tile) {
-                                neighbouring_plates.push(i);
-                                break 'lp;
-                            }
-                        }
+        merges
+    }
+}
 
-                    }
+/// Plate that `idx` ended up merged into, shortening the chain of merges on the way.
+fn find_merged(merged_into: &mut [usize], mut idx: usize) -> usize {
+    while merged_into[idx] != idx {
+        merged_into[idx] = merged_into[merged_into[idx]];
+        idx = merged_into[idx];
+    }
+    idx
+}
+
+/// Distance to keep between Poisson-disc seeds on the unit sphere, three quarters of the
+/// spacing `num_plates` points would have packed hexagonally, since random darts rarely
+/// fill the sphere much tighter than that.
+fn poisson_disc_separation(num_plates: usize) -> f32 {
+    let area_per_plate = 4.0 * f32::consts::PI / num_plates.max(1) as f32;
+    0.75 * (2.0 * area_per_plate / 3.0f32.sqrt()).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use terrain::generator::Generator;
+    use math::DotProduct;
+    use super::*;
+
+    #[test]
+    fn merged_plates_have_contiguous_ids() {
+        let mut gen = Generator::new(11);
+        gen.subdivide();
+        gen.subdivide();
+        let mut planet = gen.to_planet();
+
+        planet.merge_plates();
+
+        for (idx, tile) in planet.tiles_iter().enumerate() {
+            assert!(tile.plate_id >= 1 && tile.plate_id as usize <= planet.num_plates(),
+                    "Tile refers to plate {} of {}",
+                    tile.plate_id,
+                    planet.num_plates());
+            let plate = &planet.plates[tile.plate_id as usize - 1];
+            assert!(plate.tiles.contains(&(idx as TileIndex)));
+        }
+    }
+
+    #[test]
+    fn sea_level_hits_target_land_fraction() {
+        let mut gen = Generator::new(11);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let mut planet = gen.to_planet();
+
+        for target in [0.0, 0.29, 0.5, 1.0].iter() {
+            let sea_level = planet.sea_level_for_land_fraction(*target);
+            planet.set_sea_level(sea_level);
+            assert!((planet.land_fraction() - target).abs() <= 1.0 / planet.num_tiles() as f32,
+                    "Wanted {} land, got {}",
+                    target,
+                    planet.land_fraction());
+        }
+    }
+
+    #[test]
+    fn surface_settings_apply_together() {
+        let mut gen = Generator::new(11);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let mut separately = gen.to_planet();
+        let mut together = gen.to_planet();
+        let sea_level = separately.sea_level_for_land_fraction(0.4);
+        let climate = ClimateConfig { axial_tilt: 60.0, ..ClimateConfig::default() };
+        let rules = BiomeRules::parse("desert .. .. 0..").unwrap();
+
+        separately.set_sea_level(sea_level);
+        separately.set_climate_config(climate);
+        separately.set_biome_rules(rules.clone());
+        separately.update_surface();
+        together.set_surface(sea_level, climate, rules);
+
+        assert_eq!(together.sea_level(), separately.sea_level());
+        assert_eq!(together.climate().temperature, separately.climate().temperature);
+        assert_eq!(together.biomes, separately.biomes);
+        assert_eq!(together.water_bodies(), separately.water_bodies());
+    }
+
+    #[test]
+    fn coasts_and_biomes_follow_the_sea_level() {
+        let mut gen = Generator::new(11);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let mut planet = gen.to_planet();
+        let sea_level = planet.sea_level_for_land_fraction(0.29);
+
+        planet.set_sea_level(sea_level);
+        planet.update_surface();
+
+        let (depth, height) = planet.get_elevation_scale();
+        assert!(depth > 0.0 && height > 0.0);
+        for (idx, tile) in planet.tiles_iter().enumerate() {
+            let ocean = planet.tile_elevation(tile) < sea_level;
+            assert_eq!(planet.is_ocean(tile), ocean);
+            assert_eq!(planet.climate().coast_distance[idx] == 0.0, ocean);
+            if ocean {
+                assert!(planet.hydrology().downstream[idx].is_none());
+                match planet.tile_biome(tile) {
+                    Some(Biome::DeepOcean) | Some(Biome::ShallowSea) | Some(Biome::SeaIce) => {}
+                    other => panic!("Ocean tile classified as {:?}", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn merged_plates_have_consistent_borders_and_movement() {
+        let mut gen = Generator::new(11);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let mut planet = gen.to_planet();
+        let num_plates = planet.num_plates();
+
+        let merges = planet.merge_plates();
+
+        assert!(!merges.is_empty());
+        assert_eq!(planet.num_plates(), num_plates - merges.len());
+        for merge in merges.iter() {
+            assert!(merge.absorbed_tiles <= merge.into_tiles);
+        }
+        for plate in planet.plates.iter() {
+            for t in plate.tiles.iter() {
+                let tile = planet.tile(*t);
+                assert_eq!(tile.plate_id, plate.id);
+                assert_eq!(tile.movement_vector, planet.calculate_movement_vector(plate, tile));
+            }
+            for (idx, border) in planet.borders_iter().enumerate() {
+                let a = planet.tile(border.tiles.0).plate_id;
+                let b = planet.tile(border.tiles.1).plate_id;
+                let on_plate = a != b && (a == plate.id || b == plate.id);
+                assert_eq!(plate.borders.contains(&(idx as BorderIndex)), on_plate);
+            }
+        }
+    }
+
+    #[test]
+    fn last_plate_is_kept_without_neighbours() {
+        let mut gen = Generator::new(11);
+        gen.subdivide();
+        gen.subdivide();
+        let config = PlateConfig {
+            num_plates: 4,
+            min_plate_size: 2.0,
+            ..PlateConfig::default()
+        };
+        let mut planet = gen.to_planet_with(config);
+
+        let merges = planet.merge_plates();
+
+        assert_eq!(merges.len(), 3);
+        assert_eq!(planet.num_plates(), 1);
+        assert_eq!(planet.plates[0].tiles.len(), planet.num_tiles());
+    }
+
+    #[test]
+    fn plates_follow_config() {
+        let mut gen = Generator::new(11);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let config = PlateConfig {
+            num_plates: 12,
+            min_plate_size: 0.05,
+            ..PlateConfig::default()
+        };
+        let mut planet = gen.to_planet_with(config);
+        assert_eq!(planet.num_plates(), 12);
+
+        planet.merge_plates();
+
+        let min_plate_size = (planet.num_tiles() as f32 * 0.05) as usize;
+        for plate in planet.plates.iter() {
+            assert!(plate.tiles.len() >= min_plate_size);
+        }
+    }
+
+    #[test]
+    fn degenerate_config_still_grows_plates() {
+        let mut gen = Generator::new(11);
+        gen.subdivide();
+        let config = PlateConfig {
+            num_plates: 0,
+            growth_bias: -1.0,
+            ..PlateConfig::default()
+        };
+
+        let planet = gen.to_planet_with(config);
+
+        assert_eq!(planet.num_plates(), 1);
+        assert!(planet.tiles_iter().all(|t| t.plate_id == 1));
+    }
+
+    #[test]
+    fn poisson_disc_seeds_spread_out() {
+        let mut gen = Generator::new(11);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        // Plates list their seed tiles first
+        let closest_seeds = |seeding| {
+            let config = PlateConfig { seeding, ..PlateConfig::default() };
+            let planet = gen.to_planet_with(config);
+            let seeds: Vec<Vertex> = planet.plates
+                .iter()
+                .map(|p| planet.tile_normal(planet.tile(p.tiles[0])))
+                .collect();
+            let mut closest = f32::MAX;
+            for (i, a) in seeds.iter().enumerate() {
+                for b in seeds[i + 1..].iter() {
+                    closest = closest.min(distance(a, b));
                 }
             }
+            closest
+        };
 
-            // merge with neighbouring plate
-            let other_plate_idx = neighbouring_plates[0];
-            println!("Merging plate {} size {} with plate {} size {}",
-                     plates[0].id,
-                     plates[0].tiles.len(),
-                     plates[other_plate_idx].id,
-                     plates[other_plate_idx].tiles.len());
-            let mut tiles = plates[0].tiles.clone();
-            plates[other_plate_idx].tiles.append(&mut tiles);
-            plates.swap_remove(0);
-            //break;
+        let poisson = closest_seeds(Seeding::PoissonDisc);
+        assert!(poisson > closest_seeds(Seeding::Random));
+        assert!(poisson > 0.5 * poisson_disc_separation(27));
+    }
+
+    #[test]
+    fn only_borders_between_plates_have_boundaries() {
+        let mut gen = Generator::new(11);
+        gen.subdivide();
+        gen.subdivide();
+        let mut planet = gen.to_planet();
+        planet.merge_plates();
+
+        let mut num_boundaries = 0;
+        for border in planet.borders_iter() {
+            let a = planet.tile(border.tiles.0).plate_id;
+            let b = planet.tile(border.tiles.1).plate_id;
+            assert_eq!(border.boundary.is_some(), a != b);
+            if border.boundary.is_some() {
+                num_boundaries += 1;
+            }
         }
+        assert!(num_boundaries > 0);
+    }
 
-        self.plates = plates;
+    #[test]
+    fn tile_at_finds_nearest_midpoint() {
+        let mut gen = Generator::new(13);
+        gen.subdivide();
+        gen.subdivide();
+        gen.introduce_chaos(0.1);
+        let planet = gen.to_planet();
+
+        for tile in planet.tiles_iter() {
+            let midpoint = planet.tile_midpoint(tile);
+            assert_eq!(planet.tile_at(&midpoint), tile.midpoint - planet.num_corners as u32);
+        }
+    }
 
-        self.assign_plates();
+    #[test]
+    fn tile_at_matches_brute_force() {
+        let mut gen = Generator::new(13);
+        gen.subdivide();
+        gen.subdivide();
+        gen.introduce_chaos(0.1);
+        let planet = gen.to_planet();
+
+        let mut rng = TerrainRng::new(5);
+        for _ in 0..200 {
+            let direction = normalize(Vertex::new(rng.gen_range(-1.0, 1.0),
+                                                  rng.gen_range(-1.0, 1.0),
+                                                  rng.gen_range(-1.0, 1.0)));
+            let nearest = planet.tiles_iter()
+                .map(|t| normalize(planet.tile_midpoint(t)).dot(&direction))
+                .enumerate()
+                .fold((0, f32::MIN), |a, (i, d)| if d > a.1 { (i, d) } else { a });
+
+            assert_eq!(planet.tile_at(&direction) as usize, nearest.0);
+        }
     }
 }
@@ -0,0 +1,236 @@
+use math::{DotProduct, normalize};
+
+use super::{Planet, Tile, TileIndex, Vertex};
+
+/// Rounds of evening out the currents between neighbouring tiles, which lets them form
+/// gyres and follow the coasts some way from where the wind pushes them.
+const CURRENT_SMOOTHING: usize = 8;
+
+/// Distance in km along a current over which the water keeps two thirds of the
+/// temperature difference to where it came from.
+const HEAT_DISTANCE: f32 = 2000.0;
+
+/// Where sea water freezes, in °C.
+pub(super) const FREEZING_POINT: f32 = -1.8;
+
+/// Surface current of every ocean tile, zero on land. The wind drives the water, which
+/// turns along the coast instead of running into land and evens out with the water
+/// around it.
+pub fn currents(normals: &[Vertex],
+                elevations: &[f32],
+                neighbours: &[Vec<TileIndex>],
+                sea_level: f32,
+                wind: &[Vertex])
+                -> Vec<Vertex> {
+    let num_tiles = normals.len();
+    let is_ocean = |t: usize| elevations[t] < sea_level;
+    // Directions along the surface towards the land around each ocean tile
+    let coasts: Vec<Vec<Vertex>> = (0..num_tiles)
+        .map(|t| if is_ocean(t) {
+            neighbours[t]
+                .iter()
+                .map(|n| *n as usize)
+                .filter(|n| !is_ocean(*n))
+                .map(|n| along_surface(&normals[t], &normals[n] - &normals[t]))
+                .collect()
+        } else {
+            Vec::new()
+        })
+        .collect();
+
+    let mut current: Vec<Vertex> = (0..num_tiles)
+        .map(|t| if is_ocean(t) {
+            deflect(wind[t].clone(), &coasts[t])
+        } else {
+            Vertex::origo()
+        })
+        .collect();
+    for _ in 0..CURRENT_SMOOTHING {
+        current = (0..num_tiles)
+            .map(|t| {
+                if !is_ocean(t) {
+                    return Vertex::origo();
+                }
+                let mut sum = wind[t].clone();
+                let mut count = 1.0;
+                for n in neighbours[t].iter().map(|n| *n as usize).filter(|n| is_ocean(*n)) {
+                    sum += &current[n];
+                    count += 1.0;
+                }
+                let v = &normals[t];
+                let average = sum / count;
+                let flat = &average - v * average.dot(v);
+                deflect(flat, &coasts[t])
+            })
+            .collect();
+    }
+    current
+}
+
+/// Part of `v` along the surface at `normal`, as a unit vector.
+fn along_surface(normal: &Vertex, v: Vertex) -> Vertex {
+    let d = v.dot(normal);
+    normalize(v - normal * d)
+}
+
+/// Turns a current running into land along the coast, keeping its speed.
+fn deflect(mut current: Vertex, coasts: &[Vertex]) -> Vertex {
+    let speed = current.length();
+    for d in coasts.iter() {
+        let into_land = current.dot(d);
+        if into_land > 0.0 {
+            current = &current - d * into_land;
+        }
+    }
+    let remaining = current.length();
+    if remaining > 1e-6 {
+        current * (speed / remaining)
+    } else {
+        current
+    }
+}
+
+/// Water temperature of every ocean tile, `local` on land. Currents carry the water
+/// with them, so it is warmer than the latitude alone would make it where it flows from
+/// the tropics and colder where it comes from near the poles.
+pub fn sea_temperatures(normals: &[Vertex],
+                        elevations: &[f32],
+                        neighbours: &[Vec<TileIndex>],
+                        sea_level: f32,
+                        current: &[Vertex],
+                        local: &[f32],
+                        tile_km: f32)
+                        -> Vec<f32> {
+    let num_tiles = normals.len();
+    let is_ocean = |t: usize| elevations[t] < sea_level;
+
+    // The neighbour whose current brings it the most water
+    let upstream: Vec<Option<usize>> = (0..num_tiles)
+        .map(|t| {
+            if !is_ocean(t) {
+                return None;
+            }
+            neighbours[t]
+                .iter()
+                .map(|n| *n as usize)
+                .filter(|n| is_ocean(*n))
+                .map(|n| (n, current[n].dot(&normalize(&normals[t] - &normals[n]))))
+                .filter(|&(_, inflow)| inflow > 0.05)
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .map(|(n, _)| n)
+        })
+        .collect();
+
+    // Weighted mean of the local temperatures back along the current, each tile upstream
+    // weighing less by `keep`. Jumping twice as far every round sums up long stretches
+    // of current in a few rounds, around gyres too.
+    let keep = (-tile_km / HEAT_DISTANCE).exp();
+    let steps = 3.0 * HEAT_DISTANCE / tile_km;
+    let mut sum = local.to_vec();
+    let mut weight = vec![1.0; num_tiles];
+    let mut jump = upstream;
+    let mut factor = keep;
+    let mut reach = 1.0;
+    while reach < steps {
+        let mut next_sum = sum.clone();
+        let mut next_weight = weight.clone();
+        let mut next_jump = vec![None; num_tiles];
+        for t in 0..num_tiles {
+            if let Some(j) = jump[t] {
+                next_sum[t] += factor * sum[j];
+                next_weight[t] += factor * weight[j];
+                next_jump[t] = jump[j];
+            }
+        }
+        sum = next_sum;
+        weight = next_weight;
+        jump = next_jump;
+        factor *= factor;
+        reach *= 2.0;
+    }
+
+    (0..num_tiles)
+        .map(|t| if is_ocean(t) {
+            sum[t] / weight[t]
+        } else {
+            local[t]
+        })
+        .collect()
+}
+
+impl Planet {
+    /// Surface current of an ocean tile, zero on land.
+    pub fn tile_current(&self, tile: &Tile) -> &Vertex {
+        &self.climate.current[self.tile_index(tile)]
+    }
+
+    /// Temperature of the water at the surface of an ocean tile in °C, never below
+    /// freezing, and the air temperature on land.
+    pub fn tile_sea_temperature(&self, tile: &Tile) -> f32 {
+        self.climate.sea_temperature[self.tile_index(tile)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::Vec3;
+
+    /// Tiles around the equator, eastwards, with land on those at or above zero.
+    fn equator(elevations: &[f32]) -> (Vec<Vertex>, Vec<Vec<TileIndex>>) {
+        let n = elevations.len();
+        let normals = (0..n)
+            .map(|i| {
+                let longitude = 2.0 * ::std::f32::consts::PI * i as f32 / n as f32;
+                Vec3::new(longitude.cos(), 0.0, longitude.sin())
+            })
+            .collect();
+        let neighbours = (0..n)
+            .map(|i| vec![((i + n - 1) % n) as TileIndex, ((i + 1) % n) as TileIndex])
+            .collect();
+        (normals, neighbours)
+    }
+
+    #[test]
+    fn currents_turn_along_coasts() {
+        let elevations = [-100.0, -100.0, 100.0, -100.0];
+        let (normals, neighbours) = equator(&elevations);
+        // Wind blowing east and a little north, onto the land at tile 2
+        let wind: Vec<Vertex> = normals.iter()
+            .map(|n| Vec3::new(-n.z, 0.3, n.x))
+            .collect();
+
+        let current = currents(&normals, &elevations, &neighbours, 0.0, &wind);
+
+        assert_eq!(current[2], Vertex::origo());
+        let towards_land = normalize(&normals[2] - &normals[1]);
+        assert!(current[1].dot(&towards_land) < 1e-5);
+        assert!(current[1].y > 0.0);
+        assert!(current[0].dot(&normalize(&normals[1] - &normals[0])) > 0.0);
+    }
+
+    #[test]
+    fn currents_carry_heat() {
+        let elevations = [-100.0; 6];
+        let (normals, neighbours) = equator(&elevations);
+        // Flowing east, from the warm tile 0 to tile 1
+        let current: Vec<Vertex> = normals.iter().map(|n| Vec3::new(-n.z, 0.0, n.x)).collect();
+        let local = [20.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+
+        let sea = sea_temperatures(&normals,
+                                   &elevations,
+                                   &neighbours,
+                                   0.0,
+                                   &current,
+                                   &local,
+                                   500.0);
+
+        assert!(sea[1] > 4.0);
+        assert!(sea[2] > 0.0 && sea[2] < sea[1]);
+        assert!(sea[0] < 20.0);
+        // Each tile around the ring keeps less of the warmth
+        for t in 2..6 {
+            assert!(sea[t] < sea[t - 1]);
+        }
+    }
+}
@@ -0,0 +1,450 @@
+use math::TerrainRng;
+use terrain::codec::*;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::{Planet, Tile, Border, Plate, CrustType, BorderIndex, TileIndex, VertexIndex};
+use super::{ClimateConfig, PlanetParts, PlateConfig};
+
+const MAGIC: &[u8; 4] = b"TGPL";
+const VERSION: u32 = 1;
+
+impl Planet {
+    /// Writes the complete planet: geometry, tiles, borders, corner elevations, plates, the
+    /// tile elevations left by tectonics and erosion, crust ages, the climate settings and
+    /// the sea level. Biome rules are not saved, loaded planets classify their tiles with the
+    /// default rules.
+    pub fn save<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_header(out, MAGIC, VERSION)?;
+
+        for word in self.rng.state().iter() {
+            out.write_u32::<LittleEndian>(*word)?;
+        }
+        out.write_f32::<LittleEndian>(self.scale)?;
+        out.write_f32::<LittleEndian>(self.config.ocean_ratio)?;
+
+        write_len(out, self.num_corners)?;
+        write_len(out, self.num_tiles)?;
+        for v in self.vertices.iter() {
+            write_vec3(out, v)?;
+        }
+        for e in self.elevations.iter() {
+            out.write_f32::<LittleEndian>(*e)?;
+        }
+
+        for tile in self.tiles.iter() {
+            let vertices: Vec<VertexIndex> = tile.vertices_iter().cloned().collect();
+            write_indices(out, &vertices)?;
+            out.write_u32::<LittleEndian>(tile.midpoint)?;
+            write_indices(out, &tile.borders)?;
+            out.write_u32::<LittleEndian>(tile.plate_id)?;
+            write_vec3(out, &tile.movement_vector)?;
+        }
+
+        write_len(out, self.borders.len())?;
+        for border in self.borders.iter() {
+            out.write_u32::<LittleEndian>(border.vertices.0)?;
+            out.write_u32::<LittleEndian>(border.vertices.1)?;
+            out.write_u32::<LittleEndian>(border.tiles.0)?;
+            out.write_u32::<LittleEndian>(border.tiles.1)?;
+        }
+
+        write_len(out, self.plates.len())?;
+        for plate in self.plates.iter() {
+            out.write_u32::<LittleEndian>(plate.id)?;
+            write_vec3(out, &plate.axis_of_rotation)?;
+            out.write_f32::<LittleEndian>(plate.angular_velocity)?;
+            out.write_u8(match plate.crust_type {
+                    CrustType::Oceanic => 0,
+                    CrustType::Continental => 1,
+                })?;
+            out.write_f32::<LittleEndian>(plate.density)?;
+            write_indices(out, &plate.tiles)?;
+            let mut borders: Vec<BorderIndex> = plate.borders.iter().cloned().collect();
+            borders.sort();
+            write_indices(out, &borders)?;
+        }
+
+        for e in self.tile_elevations.iter() {
+            out.write_f32::<LittleEndian>(*e)?;
+        }
+        for tile in self.tiles.iter() {
+            out.write_f32::<LittleEndian>(tile.crust_age)?;
+        }
+
+        let climate = &self.climate_config;
+        for value in [climate.axial_tilt,
+                      climate.equator_temperature,
+                      climate.pole_temperature,
+                      climate.lapse_rate,
+                      climate.ocean_moderation,
+                      climate.moderation_distance,
+                      climate.ocean_evaporation,
+                      climate.rain_distance,
+                      climate.orographic_height,
+                      climate.recycling]
+            .iter() {
+            out.write_f32::<LittleEndian>(*value)?;
+        }
+        out.write_f32::<LittleEndian>(self.sea_level)?;
+
+        Ok(())
+    }
+
+    /// Loads a planet written by `save`, checking that its indices hold together. The
+    /// climate, biomes and drainage are not stored and wait for `update_surface` or
+    /// `set_surface`.
+    pub fn load<R: Read>(input: &mut R) -> io::Result<Planet> {
+        read_header(input, MAGIC, VERSION)?;
+
+        let mut state = [0u32; 4];
+        for word in state.iter_mut() {
+            *word = input.read_u32::<LittleEndian>()?;
+        }
+        let rng = TerrainRng::from_state(state)
+            .ok_or_else(|| invalid_data("Invalid random generator state".to_string()))?;
+        let scale = input.read_f32::<LittleEndian>()?;
+        let ocean_ratio = input.read_f32::<LittleEndian>()?;
+        // The plates are stored as grown, only regrowing them needs the rest of the config
+        let config = PlateConfig { ocean_ratio, ..PlateConfig::default() };
+
+        let num_corners = read_len(input)?;
+        let num_tiles = read_len(input)?;
+        let num_vertices = num_corners + num_tiles;
+        let mut vertices = Vec::with_capacity(preallocation(num_vertices));
+        for _ in 0..num_vertices {
+            vertices.push(read_vec3(input)?);
+        }
+        let mut elevations = Vec::with_capacity(preallocation(num_corners));
+        for _ in 0..num_corners {
+            elevations.push(input.read_f32::<LittleEndian>()?);
+        }
+
+        // Border and plate counts are not known yet, so their indices are checked afterwards
+        let mut tiles = Vec::with_capacity(preallocation(num_tiles));
+        for idx in 0..num_tiles {
+            let tile_vertices = read_indices(input, num_corners)?;
+            if tile_vertices.len() < 3 {
+                return Err(invalid_data(format!("Tile with only {} vertices",
+                                                tile_vertices.len())));
+            }
+            // Tiles find their index from their midpoint, which follows the corners in order
+            let midpoint = input.read_u32::<LittleEndian>()?;
+            if midpoint as usize != num_corners + idx {
+                return Err(invalid_data(format!("Tile {} has midpoint {}, expected {}",
+                                                idx,
+                                                midpoint,
+                                                num_corners + idx)));
+            }
+            let mut tile = Tile::new(tile_vertices, midpoint);
+            tile.borders = read_indices(input, u32::MAX as usize)?;
+            tile.plate_id = input.read_u32::<LittleEndian>()?;
+            tile.movement_vector = read_vec3(input)?;
+            tiles.push(tile);
+        }
+
+        let num_borders = read_len(input)?;
+        let mut borders = Vec::with_capacity(preallocation(num_borders));
+        for _ in 0..num_borders {
+            let va = read_index(input, num_corners)?;
+            let vb = read_index(input, num_corners)?;
+            let ta = read_index(input, num_tiles)?;
+            let tb = read_index(input, num_tiles)?;
+            borders.push(Border::new(va, vb, ta, tb));
+        }
+
+        let num_plates = read_len(input)?;
+        let mut plates = Vec::with_capacity(preallocation(num_plates));
+        for _ in 0..num_plates {
+            let id = input.read_u32::<LittleEndian>()?;
+            let axis_of_rotation = read_vec3(input)?;
+            let angular_velocity = input.read_f32::<LittleEndian>()?;
+            let crust_type = match input.read_u8()? {
+                0 => CrustType::Oceanic,
+                1 => CrustType::Continental,
+                other => return Err(invalid_data(format!("Unknown crust type {}", other))),
+            };
+            let density = input.read_f32::<LittleEndian>()?;
+            let plate_tiles = read_indices(input, num_tiles)?;
+            let plate_borders: HashSet<BorderIndex> =
+                read_indices(input, num_borders)?.into_iter().collect();
+            plates.push(Plate {
+                id,
+                tiles: plate_tiles,
+                borders: plate_borders,
+                axis_of_rotation,
+                angular_velocity,
+                crust_type,
+                density,
+                frame: HashSet::new(),
+                rotation: 0.0,
+            });
+        }
+
+        let mut tile_elevations = Vec::with_capacity(preallocation(num_tiles));
+        for _ in 0..num_tiles {
+            tile_elevations.push(input.read_f32::<LittleEndian>()?);
+        }
+        // Moving plates age the crust, so it can not be told from the plates again
+        for tile in tiles.iter_mut() {
+            tile.crust_age = input.read_f32::<LittleEndian>()?;
+        }
+
+        let climate_config = ClimateConfig {
+            axial_tilt: input.read_f32::<LittleEndian>()?,
+            equator_temperature: input.read_f32::<LittleEndian>()?,
+            pole_temperature: input.read_f32::<LittleEndian>()?,
+            lapse_rate: input.read_f32::<LittleEndian>()?,
+            ocean_moderation: input.read_f32::<LittleEndian>()?,
+            moderation_distance: input.read_f32::<LittleEndian>()?,
+            ocean_evaporation: input.read_f32::<LittleEndian>()?,
+            rain_distance: input.read_f32::<LittleEndian>()?,
+            orographic_height: input.read_f32::<LittleEndian>()?,
+            recycling: input.read_f32::<LittleEndian>()?,
+        };
+        let sea_level = input.read_f32::<LittleEndian>()?;
+
+        for (idx, tile) in tiles.iter().enumerate() {
+            for b in tile.borders.iter() {
+                let border = borders.get(*b as usize)
+                    .ok_or_else(|| invalid_data("Tile refers to a missing border".to_string()))?;
+                if border.other_tile(idx as TileIndex).is_none() {
+                    return Err(invalid_data(format!("Tile {} refers to border {}, which does \
+                                                     not refer back",
+                                                    idx,
+                                                    b)));
+                }
+            }
+            if tile.plate_id == 0 || tile.plate_id as usize > num_plates {
+                return Err(invalid_data(format!("Tile refers to missing plate {}",
+                                                tile.plate_id)));
+            }
+        }
+        // Every tile must be listed once, by the plate it refers to
+        let mut listed = vec![false; num_tiles];
+        for (idx, plate) in plates.iter().enumerate() {
+            if plate.id as usize != idx + 1 {
+                return Err(invalid_data(format!("Plate {} has id {}", idx + 1, plate.id)));
+            }
+            for t in plate.tiles.iter() {
+                if tiles[*t as usize].plate_id != plate.id || listed[*t as usize] {
+                    return Err(invalid_data(format!("Plate {} lists tile {}, which is not \
+                                                     its own",
+                                                    plate.id,
+                                                    t)));
+                }
+                listed[*t as usize] = true;
+            }
+        }
+        if let Some(t) = listed.iter().position(|l| !l) {
+            return Err(invalid_data(format!("Tile {} is on no plate's list", t)));
+        }
+
+        let mut planet = Planet::from_parts(PlanetParts {
+            vertices,
+            tiles,
+            borders,
+            elevations,
+            plates,
+            scale,
+            config,
+            rng,
+        });
+        // Erosion is not repeated on loading, so its result overrides the uplift
+        planet.tile_elevations = tile_elevations;
+        planet.climate_config = climate_config;
+        planet.sea_level = sea_level;
+        Ok(planet)
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.save(&mut out)?;
+        out.flush()
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> io::Result<Planet> {
+        Planet::load(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use terrain::generator::Generator;
+    use terrain::planet::ErosionConfig;
+    use super::*;
+
+    fn saved_planet() -> (Planet, Vec<u8>) {
+        let mut gen = Generator::new(23);
+        gen.subdivide();
+        gen.subdivide();
+        let mut planet = gen.to_planet();
+        planet.merge_plates();
+
+        let mut buf = Vec::new();
+        planet.save(&mut buf).unwrap();
+        (planet, buf)
+    }
+
+    #[test]
+    fn planet_round_trip() {
+        let (planet, buf) = saved_planet();
+
+        let loaded = Planet::load(&mut &buf[..]).unwrap();
+
+        assert_eq!(loaded.num_tiles(), planet.num_tiles());
+        assert_eq!(loaded.num_plates(), planet.num_plates());
+        for (a, b) in planet.tiles_iter().zip(loaded.tiles_iter()) {
+            assert_eq!(a.plate_id, b.plate_id);
+            assert_eq!(a.borders, b.borders);
+            assert_eq!(a.movement_vector, b.movement_vector);
+            assert_eq!(planet.tile_border_points(a), loaded.tile_border_points(b));
+            assert_eq!(planet.tile_elevation(a), loaded.tile_elevation(b));
+            assert_eq!(a.crust_age, b.crust_age);
+            assert_eq!(a.crust_thickness, b.crust_thickness);
+        }
+        assert_eq!(planet.tile_neighbours, loaded.tile_neighbours);
+        assert_eq!(planet.vertex_to_tiles, loaded.vertex_to_tiles);
+        for (a, b) in planet.plates.iter().zip(loaded.plates.iter()) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.tiles, b.tiles);
+            assert_eq!(a.borders, b.borders);
+            assert_eq!(a.axis_of_rotation, b.axis_of_rotation);
+            assert_eq!(a.angular_velocity, b.angular_velocity);
+            assert_eq!(a.crust_type, b.crust_type);
+            assert_eq!(a.density, b.density);
+        }
+
+        let mut resaved = Vec::new();
+        loaded.save(&mut resaved).unwrap();
+        assert!(resaved == buf, "Saving a loaded planet should give identical bytes");
+    }
+
+    #[test]
+    fn erosion_survives_round_trip() {
+        let (mut planet, _) = saved_planet();
+        planet.erode(&ErosionConfig { iterations: 3, ..ErosionConfig::default() });
+        planet.update_surface();
+        let mut buf = Vec::new();
+        planet.save(&mut buf).unwrap();
+
+        let mut loaded = Planet::load(&mut &buf[..]).unwrap();
+        loaded.update_surface();
+
+        for (a, b) in planet.tiles_iter().zip(loaded.tiles_iter()) {
+            assert_eq!(planet.tile_elevation(a), loaded.tile_elevation(b));
+        }
+        assert_eq!(planet.water_bodies(), loaded.water_bodies());
+    }
+
+    #[test]
+    fn climate_settings_survive_round_trip() {
+        let (mut planet, _) = saved_planet();
+        planet.set_climate_config(ClimateConfig {
+            axial_tilt: 45.0,
+            recycling: 0.2,
+            ..ClimateConfig::default()
+        });
+        planet.update_surface();
+        let mut buf = Vec::new();
+        planet.save(&mut buf).unwrap();
+
+        let mut loaded = Planet::load(&mut &buf[..]).unwrap();
+        loaded.update_surface();
+
+        assert_eq!(loaded.climate_config(), planet.climate_config());
+        assert_eq!(loaded.climate().temperature, planet.climate().temperature);
+        assert_eq!(loaded.climate().precipitation, planet.climate().precipitation);
+    }
+
+    #[test]
+    fn sea_level_survives_round_trip() {
+        let (mut planet, _) = saved_planet();
+        let sea_level = planet.sea_level_for_land_fraction(0.29);
+        planet.set_sea_level(sea_level);
+        planet.update_surface();
+        let mut buf = Vec::new();
+        planet.save(&mut buf).unwrap();
+
+        let mut loaded = Planet::load(&mut &buf[..]).unwrap();
+        loaded.update_surface();
+
+        assert_eq!(loaded.sea_level(), sea_level);
+        assert_eq!(loaded.land_fraction(), planet.land_fraction());
+        assert_eq!(loaded.water_bodies(), planet.water_bodies());
+    }
+
+    /// Where the midpoint of the first tile is stored: after the header, rng state, scale,
+    /// ocean ratio, counts, vertices, corner elevations and the tile's vertices.
+    fn first_midpoint_offset(planet: &Planet) -> usize {
+        8 + 16 + 4 + 4 + 8 + 12 * planet.vertices.len() + 4 * planet.num_corners + 4 +
+        4 * planet.tile(0).vertices_iter().count()
+    }
+
+    fn rejects(buf: &[u8]) {
+        let err = Planet::load(&mut &buf[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn misplaced_midpoint_is_rejected() {
+        let (planet, mut buf) = saved_planet();
+        let offset = first_midpoint_offset(&planet);
+        assert_eq!((&buf[offset..]).read_u32::<LittleEndian>().unwrap(),
+                   planet.tile(0).midpoint);
+
+        (&mut buf[offset..]).write_u32::<LittleEndian>(0).unwrap();
+
+        rejects(&buf);
+    }
+
+    #[test]
+    fn border_that_does_not_link_back_is_rejected() {
+        let (planet, mut buf) = saved_planet();
+        let tile = planet.tile(0);
+        let stranger = planet.borders_iter()
+            .position(|b| b.other_tile(0).is_none())
+            .unwrap();
+        let offset = first_midpoint_offset(&planet) + 4;
+        assert_eq!((&buf[offset..]).read_u32::<LittleEndian>().unwrap() as usize,
+                   tile.borders.len());
+
+        (&mut buf[offset + 4..]).write_u32::<LittleEndian>(stranger as u32).unwrap();
+
+        rejects(&buf);
+    }
+
+    #[test]
+    fn tile_on_another_plate_than_listed_is_rejected() {
+        let (planet, mut buf) = saved_planet();
+        let tile = planet.tile(0);
+        let other = if tile.plate_id == 1 { 2 } else { 1 };
+        let offset = first_midpoint_offset(&planet) + 4 + 4 + 4 * tile.borders.len();
+        assert_eq!((&buf[offset..]).read_u32::<LittleEndian>().unwrap(), tile.plate_id);
+
+        (&mut buf[offset..]).write_u32::<LittleEndian>(other).unwrap();
+
+        rejects(&buf);
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let (_, buf) = saved_planet();
+
+        assert!(Planet::load(&mut &buf[..buf.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn other_file_type_is_rejected() {
+        let (_, mut buf) = saved_planet();
+        buf[0] = b'X';
+
+        let err = Planet::load(&mut &buf[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
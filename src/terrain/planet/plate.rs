@@ -4,74 +4,130 @@ use math::normalize;
 use std::f32;
 use std::collections::HashSet;
 
-use rand::thread_rng;
 use rand::Rng;
 use rand::distributions::{IndependentSample, Range};
 
 use super::{BorderIndex, PlateIndex, TileIndex};
 
+/// Share of new plates that carry oceanic crust, unless configured otherwise.
+pub const DEFAULT_OCEAN_RATIO: f32 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrustType {
+    /// Thin, dense and deep crust, formed at spreading ridges
+    Oceanic,
+    /// Thick, light crust that floats high and resists subduction
+    Continental,
+}
+
 #[derive(Debug, Clone)]
 pub struct Plate {
     pub id: PlateIndex,
     pub tiles: Vec<TileIndex>,
     pub borders: HashSet<BorderIndex>,
-    pub base_elevation: f32,
     pub axis_of_rotation: Vec3<f32>,
     pub angular_velocity: f32,
+    pub crust_type: CrustType,
+    /// In g/cm³, the denser of two colliding plates is subducted
+    pub density: f32,
+    /// Tiles under the plate's crust when it started moving, in the plate's own frame.
+    /// Empty until the plate first moves, and again once it absorbs another plate or is
+    /// loaded from a file.
+    pub frame: HashSet<TileIndex>,
+    /// Angle the plate has turned since its frame was taken
+    pub rotation: f32,
+}
+
+/// A plate absorbed into a neighbour by `Planet::merge_plates`, with the ids the plates had
+/// before merging.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlateMerge {
+    pub absorbed: PlateIndex,
+    pub absorbed_tiles: usize,
+    pub into: PlateIndex,
+    /// Size of the receiving plate before it took in the absorbed one
+    pub into_tiles: usize,
 }
 
 impl Plate {
-    pub fn new(id: PlateIndex) -> Plate {
-        let mut rng = thread_rng();
-        let ocean_ratio = 0.6;
-        let base_elevation = if rng.next_f32() < ocean_ratio {
-            let between = Range::new(-500.0, -100.0);
-            between.ind_sample(&mut rng)
+    /// Creates an oceanic plate with probability `ocean_ratio`, and a continental one
+    /// otherwise.
+    pub fn new<R: Rng>(id: PlateIndex, ocean_ratio: f32, rng: &mut R) -> Plate {
+        let crust_type = if rng.next_f32() < ocean_ratio {
+            CrustType::Oceanic
         } else {
-            let between = Range::new(-50.0, 250.0);
-            between.ind_sample(&mut rng)
+            CrustType::Continental
+        };
+        let density = match crust_type {
+            CrustType::Oceanic => Range::new(2.9, 3.1).ind_sample(rng),
+            CrustType::Continental => Range::new(2.6, 2.8).ind_sample(rng),
         };
         let between = Range::new(-1.0, 1.0);
         let mut axis = Vec3::origo();
         while axis.length() < 0.01 {
-            axis.x = between.ind_sample(&mut rng);
-            axis.y = between.ind_sample(&mut rng);
-            axis.z = between.ind_sample(&mut rng);
+            axis.x = between.ind_sample(rng);
+            axis.y = between.ind_sample(rng);
+            axis.z = between.ind_sample(rng);
         }
         axis = normalize(axis);
-        let rotation_speed = Range::new(0.1, 0.4).ind_sample(&mut rng);
+        let rotation_speed = Range::new(0.1, 0.4).ind_sample(rng);
         Plate {
             id: id,
             tiles: Vec::new(),
             borders: HashSet::new(),
-            base_elevation: base_elevation,
             axis_of_rotation: axis,
             angular_velocity: rotation_speed,
+            crust_type: crust_type,
+            density: density,
+            frame: HashSet::new(),
+            rotation: 0.0,
         }
     }
 
+    pub fn is_oceanic(&self) -> bool {
+        self.crust_type == CrustType::Oceanic
+    }
+
     pub fn add_tile(&mut self, tile_idx: TileIndex, tile_borders: &[BorderIndex]) {
         self.tiles.push(tile_idx);
-        let tile_borders = tile_borders.iter().cloned().collect();
-        self.borders = self.borders.symmetric_difference(&tile_borders).cloned().collect();
+        // Borders shared with tiles already on the plate are now inside it
+        for b in tile_borders.iter() {
+            if !self.borders.remove(b) {
+                self.borders.insert(*b);
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use math::TerrainRng;
 
     #[test]
     fn empty_plate() {
-        let plate = Plate::new(1);
+        let plate = Plate::new(1, DEFAULT_OCEAN_RATIO, &mut TerrainRng::new(1));
         assert_eq!(plate.id, 1);
         assert_eq!(plate.tiles, Vec::new());
         assert_eq!(plate.borders, HashSet::new());
     }
 
+    #[test]
+    fn ocean_ratio_decides_crust_type() {
+        let mut rng = TerrainRng::new(1);
+        for _ in 0..20 {
+            let ocean = Plate::new(1, 1.0, &mut rng);
+            assert_eq!(ocean.crust_type, CrustType::Oceanic);
+
+            let land = Plate::new(1, 0.0, &mut rng);
+            assert_eq!(land.crust_type, CrustType::Continental);
+            assert!(land.density < ocean.density);
+        }
+    }
+
     #[test]
     fn plate_with_one_tile() {
-        let mut plate = Plate::new(1);
+        let mut plate = Plate::new(1, DEFAULT_OCEAN_RATIO, &mut TerrainRng::new(1));
         let border = vec!(2, 3, 5, 8, 13);
         plate.add_tile(17, &border);
         assert_eq!(plate.tiles, vec!(17));
@@ -80,7 +136,7 @@ mod tests {
 
     #[test]
     fn plate_with_two_tiles() {
-        let mut plate = Plate::new(1);
+        let mut plate = Plate::new(1, DEFAULT_OCEAN_RATIO, &mut TerrainRng::new(1));
         let border_1 = vec!(2, 3, 5, 8, 13);
         let border_2 = vec!(12, 13, 14, 15, 16, 17);
         plate.add_tile(1, &border_1);
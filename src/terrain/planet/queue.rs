@@ -0,0 +1,114 @@
+/// Queue that removes the element at any position of its insertion order in logarithmic
+/// time. Removed elements leave holes instead of shifting the rest, and a Fenwick tree over
+/// the slots counts the elements still present to find the n:th of them.
+pub struct GrowthQueue<T> {
+    items: Vec<Option<T>>,
+    // 1-based, every entry counts the present items in a power of two sized range of slots
+    counts: Vec<u32>,
+    len: usize,
+}
+
+impl<T> GrowthQueue<T> {
+    pub fn new() -> GrowthQueue<T> {
+        GrowthQueue {
+            items: Vec::new(),
+            counts: vec![0],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, item: T) {
+        if self.items.len() + 1 == self.counts.len() {
+            self.grow();
+        }
+        self.items.push(Some(item));
+        self.len += 1;
+        let slot = self.items.len();
+        self.add(slot, 1);
+    }
+
+    /// Removes and returns the element with `n` other elements ahead of it, or `None` if
+    /// there are not that many.
+    pub fn remove(&mut self, n: usize) -> Option<T> {
+        if n >= self.len {
+            return None;
+        }
+        let slot = self.find(n as u32 + 1);
+        self.add(slot, -1);
+        self.len -= 1;
+        self.items[slot - 1].take()
+    }
+
+    /// Slot holding the `rank`:th present element, counting from 1.
+    fn find(&self, mut rank: u32) -> usize {
+        let size = self.counts.len() - 1;
+        let mut slot = 0;
+        let mut step = size.next_power_of_two();
+        while step > 0 {
+            if slot + step <= size && self.counts[slot + step] < rank {
+                slot += step;
+                rank -= self.counts[slot];
+            }
+            step /= 2;
+        }
+        slot + 1
+    }
+
+    fn add(&mut self, mut slot: usize, delta: i32) {
+        while slot < self.counts.len() {
+            self.counts[slot] = (self.counts[slot] as i32 + delta) as u32;
+            slot += slot & slot.wrapping_neg();
+        }
+    }
+
+    /// Doubles the number of slots, rebuilding the counts in linear time.
+    fn grow(&mut self) {
+        let size = (self.counts.len() * 2).max(16);
+        let mut counts = vec![0; size + 1];
+        for slot in 1..size + 1 {
+            if self.items.get(slot - 1).is_some_and(|item| item.is_some()) {
+                counts[slot] += 1;
+            }
+            let parent = slot + (slot & slot.wrapping_neg());
+            if parent <= size {
+                counts[parent] += counts[slot];
+            }
+        }
+        self.counts = counts;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_in_insertion_order() {
+        let mut queue = GrowthQueue::new();
+        let mut expected = Vec::new();
+        for i in 0..100 {
+            queue.push(i);
+            expected.push(i);
+            if i % 3 == 0 {
+                let n = (i * 7) % expected.len();
+                assert_eq!(queue.remove(n), Some(expected.remove(n)));
+            }
+        }
+
+        assert_eq!(queue.len(), expected.len());
+        while !expected.is_empty() {
+            let n = expected.len() / 2;
+            assert_eq!(queue.remove(n), Some(expected.remove(n)));
+        }
+        assert!(queue.is_empty());
+        assert_eq!(queue.remove(0), None);
+    }
+}
@@ -0,0 +1,298 @@
+use math::{normalize, rotate};
+
+use std::f32;
+
+use super::{Planet, PlateIndex};
+use super::tectonics::{aged_crust, new_crust_age};
+
+/// What happened to the tiles during one step of plate movement.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StepReport {
+    /// Tiles that changed plate
+    pub transferred: usize,
+    /// Tiles left uncovered as plates moved apart, now filled with new crust
+    pub created: usize,
+    /// Tiles covered by more than one plate, where all but the lightest was subducted
+    pub consumed: usize,
+    /// Plates that lost all their tiles
+    pub vanished: usize,
+}
+
+/// The tile state of a planet at one point in time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub time: f32,
+    /// Plate of every tile, by tile index
+    pub plate_ids: Vec<PlateIndex>,
+    /// Elevation of every tile, by tile index
+    pub elevations: Vec<f32>,
+}
+
+impl Planet {
+    /// Time for the fastest plate to move about one tile, the largest step that does not
+    /// skip over tiles.
+    pub fn default_time_step(&self) -> f32 {
+        let tile_angle = (4.0 * f32::consts::PI / self.num_tiles as f32).sqrt();
+        let fastest = self.plates.iter().fold(0.0f32, |m, p| m.max(p.angular_velocity));
+        if fastest > 0.0 {
+            tile_angle / fastest
+        } else {
+            1.0
+        }
+    }
+
+    /// Moves every plate along its rotation by `dt` times its angular velocity.
+    ///
+    /// Tiles stay in place while plates move over them. Each plate keeps the tiles it
+    /// covered when it started moving, and a tile goes to the plate whose crust, rotated
+    /// back by the angle the plate has turned since then, was there. Rotating from that
+    /// frame rather than from the last step lets slow plates build up movement over steps
+    /// shorter than a tile. Where no plate arrives the plates are pulling apart, and the
+    /// tile gets new crust from a neighbouring oceanic plate if there is one. Where
+    /// several arrive the lightest plate overrides the others, which are subducted.
+    ///
+    /// Tiles taken over by a plate get the age of the crust that moved there, and oceanic
+    /// crust grows older as it moves. The climate, biomes and drainage are left for
+    /// `update_surface` once the plates have moved as far as wanted.
+    pub fn step(&mut self, dt: f32) -> StepReport {
+        for plate in self.plates.iter_mut() {
+            if plate.frame.is_empty() {
+                plate.frame = plate.tiles.iter().cloned().collect();
+                plate.rotation = 0.0;
+            }
+        }
+        let angles: Vec<f32> = self.plates
+            .iter()
+            .map(|p| p.rotation + p.angular_velocity * dt)
+            .collect();
+
+        let mut report = StepReport::default();
+        let old_ids: Vec<PlateIndex> = self.tiles.iter().map(|t| t.plate_id).collect();
+        let mut new_ids = old_ids.clone();
+        let old_ages: Vec<f32> = self.tiles.iter().map(|t| t.crust_age).collect();
+        let mut new_ages = old_ages.clone();
+        // Frame tiles gained as new crust and lost to subduction, applied after the step
+        let mut created = Vec::new();
+        let mut consumed = Vec::new();
+
+        for (idx, tile) in self.tiles.iter().enumerate() {
+            let mut candidates = vec![tile.plate_id];
+            for other in self.tile_neighbours[idx].iter() {
+                let id = old_ids[*other as usize];
+                if !candidates.contains(&id) {
+                    candidates.push(id);
+                }
+            }
+
+            let position = normalize(self.vertices[tile.midpoint as usize].clone());
+            let origin = |id: PlateIndex| {
+                let plate = &self.plates[id as usize - 1];
+                self.tile_at(&rotate(&position, &plate.axis_of_rotation, -angles[id as usize - 1]))
+            };
+            let arriving: Vec<PlateIndex> = candidates.iter()
+                .cloned()
+                .filter(|id| self.plates[*id as usize - 1].frame.contains(&origin(*id)))
+                .collect();
+
+            let lightest = |ids: &[PlateIndex]| {
+                ids.iter()
+                    .cloned()
+                    .min_by(|a, b| {
+                        let da = self.plates[*a as usize - 1].density;
+                        let db = self.plates[*b as usize - 1].density;
+                        da.partial_cmp(&db).unwrap()
+                    })
+                    .unwrap()
+            };
+            new_ids[idx] = match arriving.len() {
+                0 => {
+                    report.created += 1;
+                    let id = candidates.iter()
+                        .cloned()
+                        .find(|id| self.plates[*id as usize - 1].is_oceanic())
+                        .unwrap_or(tile.plate_id);
+                    created.push((id, origin(id)));
+                    new_ages[idx] = new_crust_age(&self.plates[id as usize - 1]);
+                    id
+                }
+                1 => arriving[0],
+                _ => {
+                    report.consumed += 1;
+                    let id = lightest(&arriving);
+                    for other in arriving.iter().filter(|other| **other != id) {
+                        consumed.push((*other, origin(*other)));
+                    }
+                    id
+                }
+            };
+            if new_ids[idx] != tile.plate_id {
+                report.transferred += 1;
+            }
+            if arriving.contains(&new_ids[idx]) {
+                let id = new_ids[idx];
+                let plate = &self.plates[id as usize - 1];
+                // The crust comes from where the plate was one step ago
+                let previous = self.tile_at(&rotate(&position,
+                                                    &plate.axis_of_rotation,
+                                                    -plate.angular_velocity * dt)) as usize;
+                let age = if old_ids[previous] == id {
+                    old_ages[previous]
+                } else {
+                    old_ages[idx]
+                };
+                new_ages[idx] = aged_crust(plate, age, dt);
+            }
+        }
+
+        for &(id, t) in created.iter() {
+            self.plates[id as usize - 1].frame.insert(t);
+        }
+        for &(id, t) in consumed.iter() {
+            self.plates[id as usize - 1].frame.remove(&t);
+        }
+        for (plate, angle) in self.plates.iter_mut().zip(angles) {
+            plate.rotation = angle;
+        }
+
+        for (tile, age) in self.tiles.iter_mut().zip(new_ages) {
+            tile.crust_age = age;
+        }
+
+        let num_plates = self.num_plates;
+        self.reassign_tiles(&new_ids);
+        self.uplift();
+        report.vanished = num_plates - self.num_plates;
+        report
+    }
+
+    pub fn snapshot(&self, time: f32) -> Snapshot {
+        Snapshot {
+            time,
+            plate_ids: self.tiles.iter().map(|t| t.plate_id).collect(),
+            elevations: self.tiles.iter().map(|t| self.tile_elevation(t)).collect(),
+        }
+    }
+}
+
+/// Advances a planet in fixed time steps, keeping a snapshot of every state.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    pub time_step: f32,
+    time: f32,
+    history: Vec<Snapshot>,
+}
+
+impl Simulation {
+    /// Starts at time zero with the current state of `planet` as the first snapshot.
+    pub fn new(planet: &Planet, time_step: f32) -> Simulation {
+        Simulation {
+            time_step,
+            time: 0.0,
+            history: vec![planet.snapshot(0.0)],
+        }
+    }
+
+    pub fn advance(&mut self, planet: &mut Planet) -> StepReport {
+        let report = planet.step(self.time_step);
+        self.time += self.time_step;
+        self.history.push(planet.snapshot(self.time));
+        report
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn history(&self) -> &[Snapshot] {
+        &self.history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use terrain::generator::Generator;
+    use terrain::planet::PlateConfig;
+
+    fn planet() -> Planet {
+        let mut gen = Generator::new(27);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        gen.to_planet()
+    }
+
+    #[test]
+    fn zero_step_changes_nothing() {
+        let mut planet = planet();
+        let before = planet.snapshot(0.0);
+
+        let report = planet.step(0.0);
+
+        assert_eq!(report, StepReport::default());
+        assert_eq!(planet.snapshot(0.0), before);
+    }
+
+    #[test]
+    fn steps_shorter_than_a_tile_add_up() {
+        let mut planet = planet();
+        let dt = planet.default_time_step() / 10.0;
+
+        let mut moved = 0;
+        for _ in 0..30 {
+            moved += planet.step(dt).transferred;
+        }
+
+        assert!(moved > 0);
+    }
+
+    #[test]
+    fn new_crust_is_young() {
+        let mut gen = Generator::new(27);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let config = PlateConfig { ocean_ratio: 1.0, ..PlateConfig::default() };
+        let mut planet = gen.to_planet_with(config);
+        let dt = planet.default_time_step();
+
+        let mut created = 0;
+        for _ in 0..3 {
+            let report = planet.step(dt);
+            // Older crust only ages, so the new crust is all that is left at age 0
+            let young = planet.tiles_iter().filter(|t| t.crust_age == 0.0).count();
+            assert_eq!(young, report.created);
+            created += report.created;
+        }
+        assert!(created > 0);
+    }
+
+    #[test]
+    fn plates_stay_consistent_while_moving() {
+        let mut planet = planet();
+        let dt = planet.default_time_step();
+        let mut simulation = Simulation::new(&planet, dt);
+
+        let mut moved = 0;
+        for _ in 0..5 {
+            moved += simulation.advance(&mut planet).transferred;
+        }
+
+        assert!(moved > 0);
+        assert_eq!(simulation.history().len(), 6);
+        assert!((simulation.time() - 5.0 * dt).abs() < 1e-4);
+        let num_tiles: usize = planet.plates.iter().map(|p| p.tiles.len()).sum();
+        assert_eq!(num_tiles, planet.num_tiles());
+        for (idx, plate) in planet.plates.iter().enumerate() {
+            assert_eq!(plate.id as usize, idx + 1);
+            for t in plate.tiles.iter() {
+                assert_eq!(planet.tile(*t).plate_id, plate.id);
+            }
+            for b in plate.borders.iter() {
+                let border = planet.border(*b);
+                assert!(planet.tile(border.tiles.0).plate_id !=
+                        planet.tile(border.tiles.1).plate_id);
+            }
+        }
+    }
+}
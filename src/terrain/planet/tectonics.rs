@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+
+use super::{Planet, Plate, Boundary, BoundaryType, CrustType, TileIndex};
+
+// Elevation at a plate boundary per unit of pressure or shear
+const OROGENY: f32 = 2000.0;
+const COASTAL_RANGE: f32 = 1000.0;
+const ISLAND_ARC: f32 = 1200.0;
+const TRENCH: f32 = -1500.0;
+const RIDGE: f32 = 600.0;
+const RIFT: f32 = -600.0;
+const FAULT: f32 = 150.0;
+
+// Elevation of crust away from plate boundaries: oceanic crust as it leaves the ridge
+// where it was born, continental crust in its lowlands
+const RIDGE_LEVEL: f32 = -100.0;
+const CONTINENTAL_LEVEL: f32 = 100.0;
+
+// Crust thickness in km, thickened below raised features roughly as isostasy has it
+const OCEANIC_THICKNESS: f32 = 7.0;
+const CONTINENTAL_THICKNESS: f32 = 35.0;
+const THICKENING_PER_METRE: f32 = 0.006;
+
+// Oceanic crust is born at ridges and sinks as it cools and moves away from them
+const SPREADING_KM_PER_MYR: f32 = 40.0;
+const MAX_OCEANIC_AGE: f32 = 200.0;
+const CONTINENTAL_AGE: f32 = 2000.0;
+const SUBSIDENCE: f32 = 35.0;
+
+// Radius in km of an Earth sized planet
+const RADIUS_KM: f32 = 6371.0;
+
+impl Planet {
+    /// Ages the crust of every tile as if the plates had always moved as they do now.
+    /// Oceanic crust is born at divergent boundaries and is as old as it takes to spread
+    /// to where it is, continental crust is old.
+    pub(super) fn age_crust(&mut self) {
+        let ridges: Vec<TileIndex> = self.borders
+            .iter()
+            .filter(|b| b.boundary.map(|b| b.boundary_type) == Some(BoundaryType::Divergent))
+            .flat_map(|b| vec![b.tiles.0, b.tiles.1])
+            .collect();
+        let (ridge_distance, _) = self.distances_within_plates(&ridges);
+        let tile_km = self.tile_size_km();
+
+        for (idx, tile) in self.tiles.iter_mut().enumerate() {
+            if tile.plate_id == 0 {
+                tile.crust_age = 0.0;
+                continue;
+            }
+            let plate = &self.plates[tile.plate_id as usize - 1];
+            tile.crust_age = if !plate.is_oceanic() {
+                CONTINENTAL_AGE
+            } else if ridge_distance[idx] == u32::MAX {
+                MAX_OCEANIC_AGE
+            } else {
+                let km = ridge_distance[idx] as f32 * tile_km;
+                (km / SPREADING_KM_PER_MYR).min(MAX_OCEANIC_AGE)
+            };
+        }
+    }
+
+    /// Sets the elevation and crust thickness of every tile from the type and age of its
+    /// crust. Continental crust stands at the same lowland level on every plate, oceanic
+    /// crust starts out just below sea level and deepens with the square root of its age.
+    /// The features raised or sunk along the plate boundaries are added on top, fading
+    /// with distance into the plate.
+    pub(super) fn uplift(&mut self) {
+        let offsets = self.boundary_offsets();
+
+        for (idx, tile) in self.tiles.iter_mut().enumerate() {
+            if tile.plate_id == 0 {
+                self.tile_elevations[idx] = 0.0;
+                continue;
+            }
+            let plate = &self.plates[tile.plate_id as usize - 1];
+            let elevation = match plate.crust_type {
+                CrustType::Oceanic => {
+                    tile.crust_thickness = OCEANIC_THICKNESS;
+                    RIDGE_LEVEL - SUBSIDENCE * tile.crust_age.sqrt()
+                }
+                CrustType::Continental => {
+                    tile.crust_thickness = CONTINENTAL_THICKNESS;
+                    CONTINENTAL_LEVEL
+                }
+            } + offsets[idx];
+            tile.crust_thickness = (tile.crust_thickness + offsets[idx] * THICKENING_PER_METRE)
+                .max(1.0);
+            self.tile_elevations[idx] = elevation;
+        }
+    }
+
+    /// Offset from the level of the crust for each tile. Every boundary tile keeps its
+    /// strongest feature, which spreads across the plate from the nearest boundary.
+    fn boundary_offsets(&self) -> Vec<f32> {
+        let mut feature = vec![0.0f32; self.num_tiles];
+        let mut sources = Vec::new();
+
+        for border in self.borders.iter() {
+            if let Some(ref boundary) = border.boundary {
+                let (ta, tb) = border.tiles;
+                let plate_a = &self.plates[self.tiles[ta as usize].plate_id as usize - 1];
+                let plate_b = &self.plates[self.tiles[tb as usize].plate_id as usize - 1];
+                let (ea, eb) = boundary_features(plate_a, plate_b, boundary);
+                for &(t, e) in [(ta, ea), (tb, eb)].iter() {
+                    if e.abs() > feature[t as usize].abs() {
+                        feature[t as usize] = e;
+                    }
+                    sources.push(t);
+                }
+            }
+        }
+
+        let (distance, source) = self.distances_within_plates(&sources);
+        let width = self.feature_width();
+        (0..self.num_tiles)
+            .map(|t| if distance[t] == u32::MAX {
+                0.0
+            } else {
+                feature[source[t] as usize] * (-(distance[t] as f32) / width).exp()
+            })
+            .collect()
+    }
+
+    /// Number of steps from each tile to the nearest of `sources` on the same plate, and
+    /// which source that is. Tiles that can not reach any are `u32::MAX` steps away.
+    fn distances_within_plates(&self, sources: &[TileIndex]) -> (Vec<u32>, Vec<TileIndex>) {
+        let mut distance = vec![u32::MAX; self.num_tiles];
+        let mut source: Vec<TileIndex> = (0..self.num_tiles as TileIndex).collect();
+        let mut queue = VecDeque::new();
+        for t in sources.iter() {
+            if distance[*t as usize] != 0 {
+                distance[*t as usize] = 0;
+                queue.push_back(*t);
+            }
+        }
+
+        while let Some(t) = queue.pop_front() {
+            let plate_id = self.tiles[t as usize].plate_id;
+            for other in self.tile_neighbours[t as usize].iter() {
+                let o = *other as usize;
+                if distance[o] == u32::MAX && self.tiles[o].plate_id == plate_id {
+                    distance[o] = distance[t as usize] + 1;
+                    source[o] = source[t as usize];
+                    queue.push_back(*other);
+                }
+            }
+        }
+
+        (distance, source)
+    }
+
+    /// Approximate distance between neighbouring tiles on an Earth sized planet.
+    pub(super) fn tile_size_km(&self) -> f32 {
+        (510100000.0 / self.num_tiles as f32).sqrt()
+    }
+
+    /// How many tiles it takes for a boundary feature to fall off to about a third, some
+    /// 400 km whatever the subdivision level.
+    fn feature_width(&self) -> f32 {
+        (400.0 / self.tile_size_km()).max(1.0)
+    }
+}
+
+/// Age of the crust that `plate` forms where it pulls away from another plate.
+pub(super) fn new_crust_age(plate: &Plate) -> f32 {
+    if plate.is_oceanic() {
+        0.0
+    } else {
+        CONTINENTAL_AGE
+    }
+}
+
+/// Age of crust on `plate` after it has moved for `dt`. Oceanic crust ages at the rate
+/// it spreads away from the ridges, as `Planet::age_crust` has it.
+pub(super) fn aged_crust(plate: &Plate, age: f32, dt: f32) -> f32 {
+    if plate.is_oceanic() {
+        let km = plate.angular_velocity * dt * RADIUS_KM;
+        (age + km / SPREADING_KM_PER_MYR).min(MAX_OCEANIC_AGE)
+    } else {
+        CONTINENTAL_AGE
+    }
+}
+
+/// Elevation of the features on either side of a boundary between plates `a` and `b`.
+fn boundary_features(a: &Plate, b: &Plate, boundary: &Boundary) -> (f32, f32) {
+    match boundary.boundary_type {
+        BoundaryType::Convergent => {
+            let p = boundary.pressure;
+            match (a.is_oceanic(), b.is_oceanic()) {
+                (false, false) => (OROGENY * p, OROGENY * p),
+                (true, false) => (TRENCH * p, COASTAL_RANGE * p),
+                (false, true) => (COASTAL_RANGE * p, TRENCH * p),
+                // The denser plate dives under the other
+                (true, true) if a.density > b.density => (TRENCH * p, ISLAND_ARC * p),
+                (true, true) => (ISLAND_ARC * p, TRENCH * p),
+            }
+        }
+        BoundaryType::Divergent => {
+            let s = -boundary.pressure;
+            let spread = |plate: &Plate| if plate.is_oceanic() { RIDGE * s } else { RIFT * s };
+            (spread(a), spread(b))
+        }
+        BoundaryType::Transform => (FAULT * boundary.shear, FAULT * boundary.shear),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::TerrainRng;
+    use terrain::generator::Generator;
+    use terrain::planet::PlateConfig;
+
+    fn plate(density: f32) -> Plate {
+        let mut plate = Plate::new(1, 0.0, &mut TerrainRng::new(1));
+        plate.density = density;
+        if density > 2.85 {
+            plate.crust_type = CrustType::Oceanic;
+        }
+        plate
+    }
+
+    fn boundary(boundary_type: BoundaryType, pressure: f32, shear: f32) -> Boundary {
+        Boundary {
+            boundary_type,
+            pressure,
+            shear,
+        }
+    }
+
+    #[test]
+    fn colliding_continents_raise_mountains() {
+        let (a, b) = boundary_features(&plate(2.7),
+                                       &plate(2.65),
+                                       &boundary(BoundaryType::Convergent, 0.4, 0.1));
+
+        assert!(a > 0.0 && b > 0.0);
+    }
+
+    #[test]
+    fn ocean_dives_under_continent() {
+        let (ocean, land) = boundary_features(&plate(3.0),
+                                              &plate(2.7),
+                                              &boundary(BoundaryType::Convergent, 0.4, 0.1));
+
+        assert!(ocean < 0.0);
+        assert!(land > 0.0);
+    }
+
+    #[test]
+    fn denser_ocean_plate_is_subducted() {
+        let (dense, light) = boundary_features(&plate(3.09),
+                                               &plate(2.95),
+                                               &boundary(BoundaryType::Convergent, 0.3, 0.0));
+
+        assert!(dense < 0.0);
+        assert!(light > 0.0);
+    }
+
+    #[test]
+    fn spreading_raises_ridges_in_oceans_and_rifts_continents() {
+        let (ocean, land) = boundary_features(&plate(3.0),
+                                              &plate(2.7),
+                                              &boundary(BoundaryType::Divergent, -0.3, 0.1));
+
+        assert!(ocean > 0.0);
+        assert!(land < 0.0);
+    }
+
+    #[test]
+    fn features_fade_away_from_boundaries() {
+        let mut gen = Generator::new(19);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let planet = gen.to_planet();
+
+        let offsets = planet.boundary_offsets();
+        let on_boundary: Vec<bool> = (0..planet.num_tiles())
+            .map(|t| {
+                planet.tile(t as TileIndex)
+                    .borders
+                    .iter()
+                    .any(|b| planet.border(*b).boundary.is_some())
+            })
+            .collect();
+
+        let max_boundary = offsets.iter()
+            .zip(on_boundary.iter())
+            .filter(|&(_, b)| *b)
+            .fold(0.0f32, |m, (o, _)| m.max(o.abs()));
+        assert!(max_boundary > 0.0);
+        for (o, b) in offsets.iter().zip(on_boundary.iter()) {
+            if !*b {
+                assert!(o.abs() < max_boundary);
+            }
+        }
+    }
+
+    #[test]
+    fn plates_of_a_kind_stand_at_one_level() {
+        let mut gen = Generator::new(19);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let config = PlateConfig { ocean_ratio: 0.5, ..PlateConfig::default() };
+        let planet = gen.to_planet_with(config);
+
+        let offsets = planet.boundary_offsets();
+        for (idx, tile) in planet.tiles_iter().enumerate() {
+            let level = if planet.plates[tile.plate_id as usize - 1].is_oceanic() {
+                RIDGE_LEVEL - SUBSIDENCE * tile.crust_age.sqrt()
+            } else {
+                CONTINENTAL_LEVEL
+            };
+            assert!((planet.tile_elevations[idx] - offsets[idx] - level).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn oceanic_crust_is_thin_and_young_at_ridges() {
+        let mut gen = Generator::new(19);
+        for _ in 0..3 {
+            gen.subdivide();
+        }
+        let config = PlateConfig { ocean_ratio: 0.5, ..PlateConfig::default() };
+        let planet = gen.to_planet_with(config);
+
+        for tile in planet.tiles_iter() {
+            let plate = &planet.plates[tile.plate_id as usize - 1];
+            match plate.crust_type {
+                CrustType::Oceanic => {
+                    assert!(tile.crust_thickness < 20.0);
+                    assert!(tile.crust_age <= MAX_OCEANIC_AGE);
+                }
+                CrustType::Continental => {
+                    assert!(tile.crust_thickness > 20.0);
+                    assert_eq!(tile.crust_age, CONTINENTAL_AGE);
+                }
+            }
+        }
+        for border in planet.borders_iter() {
+            if let Some(Boundary { boundary_type: BoundaryType::Divergent, .. }) = border.boundary {
+                for t in [border.tiles.0, border.tiles.1].iter() {
+                    let tile = planet.tile(*t);
+                    if planet.plates[tile.plate_id as usize - 1].is_oceanic() {
+                        assert_eq!(tile.crust_age, 0.0);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -14,6 +14,10 @@ pub struct Tile {
     pub borders: Vec<BorderIndex>,
     pub plate_id: u32,
     pub movement_vector: Vec3<f32>,
+    /// In km
+    pub crust_thickness: f32,
+    /// In millions of years
+    pub crust_age: f32,
 }
 
 impl Tile {
@@ -28,6 +32,8 @@ impl Tile {
             borders: Vec::new(),
             plate_id: 0,
             movement_vector: Vec3::origo(),
+            crust_thickness: 0.0,
+            crust_age: 0.0,
         }
     }
 
@@ -0,0 +1,139 @@
+use terragen::math::Vec3;
+use terragen::mesh::{self, DualColouring, Mesh};
+use terragen::Generator;
+use terragen::Planet;
+use na::{Vector3, Point3, Point2};
+use terragen::stopwatch::Stopwatch;
+
+use std::sync::mpsc::Sender;
+use std::thread;
+
+pub fn to_vector(v: &Vec3<f32>) -> Vector3<f32> {
+    Vector3::new(v.x, v.y, v.z)
+}
+
+pub fn to_point(v: &Vec3<f32>) -> Point3<f32> {
+    Point3::new(v.x, v.y, v.z)
+}
+
+pub enum Message {
+    Complete(Vec<Point3<f32>>,
+             Vec<Point3<u32>>,
+             Option<Vec<Vector3<f32>>>,
+             Option<Vec<Point2<f32>>>,
+             Option<Generator>,
+             Planet),
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[allow(dead_code)]
+pub enum Visualization {
+    Regular,
+    Dual,
+    Plates,
+    Boundaries,
+    Temperature,
+    Biomes,
+}
+
+/// Builds the mesh in the background, recomputing the planet's surface first if
+/// `update_surface` is set.
+pub fn generate(visualization: Visualization,
+                generator: Option<Generator>,
+                mut planet: Planet,
+                generate_wireframe: bool,
+                update_surface: bool,
+                tx: &Sender<Message>) {
+    let channel = tx.clone();
+    thread::spawn(move || {
+        let sw = Stopwatch::start_new();
+        if update_surface {
+            planet.update_surface();
+        }
+        let mesh = match visualization {
+            Visualization::Regular => {
+                match generator {
+                    Some(ref gen) => mesh::generate_regular(gen),
+                    None => {
+                        mesh::generate_dual(&planet, generate_wireframe, DualColouring::Elevation)
+                    }
+                }
+            }
+            Visualization::Dual => {
+                mesh::generate_dual(&planet, generate_wireframe, DualColouring::Elevation)
+            }
+            Visualization::Plates => {
+                mesh::generate_dual(&planet, generate_wireframe, DualColouring::Plates)
+            }
+            Visualization::Boundaries => {
+                mesh::generate_dual(&planet, generate_wireframe, DualColouring::Boundaries)
+            }
+            Visualization::Temperature => {
+                mesh::generate_dual(&planet, generate_wireframe, DualColouring::Temperature)
+            }
+            Visualization::Biomes => {
+                mesh::generate_dual(&planet, generate_wireframe, DualColouring::Biomes)
+            }
+        };
+        let mess = to_message(mesh, generator, planet);
+        info!("Generating mesh took {} ms", sw.elapsed_ms());
+        // (3568 ms, lvl 6)
+
+        channel.send(mess).unwrap();
+    });
+}
+
+fn to_message(mesh: Mesh, generator: Option<Generator>, planet: Planet) -> Message {
+    let vertices = mesh.vertices.iter().map(to_point).collect();
+    let faces = mesh.faces.iter().map(|f| Point3::new(f.x, f.y, f.z)).collect();
+    let normals = mesh.normals.iter().map(to_vector).collect();
+    let texcoords = mesh.texcoords.iter().map(|&(u, v)| Point2::new(u, v)).collect();
+
+    Message::Complete(vertices, faces, Some(normals), Some(texcoords), generator, planet)
+}
+
+pub fn generate_plate_vectors(planet: &Planet) -> (Vec<Point3<f32>>, Vec<Point3<u32>>) {
+    let (vertices, indices) = mesh::generate_plate_vectors(planet);
+
+    (vertices.iter().map(to_point).collect(), encode_wireframes(&indices).unwrap())
+}
+
+pub fn generate_currents(planet: &Planet) -> (Vec<Point3<f32>>, Vec<Point3<u32>>) {
+    let (vertices, indices) = mesh::generate_currents(planet);
+
+    (vertices.iter().map(to_point).collect(), encode_wireframes(&indices).unwrap_or_default())
+}
+
+pub fn generate_rivers(planet: &Planet) -> (Vec<Point3<f32>>, Vec<Point3<u32>>) {
+    let (vertices, indices) = mesh::generate_rivers(planet);
+
+    (vertices.iter().map(to_point).collect(), encode_wireframes(&indices).unwrap_or_default())
+}
+
+#[allow(dead_code)]
+fn encode_wireframes(wireframes: &Vec<u32>) -> Option<Vec<Point3<u32>>> {
+    let num_points = wireframes.len();
+    if num_points == 0 {
+        None
+    } else {
+        let mut encoded = Vec::with_capacity(num_points / 3 + 1);
+        let mut i = 0;
+        while i < num_points - 3 {
+            let tri = Point3::new(wireframes[i], wireframes[i + 1], wireframes[i + 2]);
+            encoded.push(tri);
+            i += 3;
+        }
+        match num_points - i {
+            2 => {
+                let tri = Point3::new(wireframes[i], wireframes[i + 1], 0);
+                encoded.push(tri);
+            }
+            1 => {
+                let tri = Point3::new(wireframes[i], 0, 0);
+                encoded.push(tri);
+            }
+            _ => {}
+        }
+        Some(encoded)
+    }
+}
@@ -0,0 +1,306 @@
+mod geom;
+mod render;
+
+use na::{Vector3, UnitQuaternion, Point2, Point3};
+use kiss3d::window::Window;
+use kiss3d::light::Light;
+use kiss3d::camera::ArcBall;
+use kiss3d::scene::SceneNode;
+use kiss3d::resource::{Mesh, Material};
+use kiss3d::text::Font;
+
+use glfw::{Action, Key, WindowEvent};
+
+use terragen::{Generator, Planet};
+use viewer::geom::*;
+use viewer::render::WireframeMaterial;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::f32;
+
+pub fn run(mut generator: Option<Generator>, mut planet: Option<Planet>) {
+    let (tx, rx) = channel();
+    // Planets regrown after subdividing keep the plate, climate and biome settings and the
+    // sea level of the first one
+    let plate_config = planet.as_ref().map(|p| *p.plate_config()).unwrap_or_default();
+    let climate_config = planet.as_ref().map(|p| *p.climate_config()).unwrap_or_default();
+    let biome_rules = planet.as_ref().map(|p| p.biome_rules().clone()).unwrap_or_default();
+    let sea_level = planet.as_ref().map(|p| p.sea_level()).unwrap_or(0.0);
+
+    let mut window = Window::new_with_size("Terragen", 900, 900);
+
+    let font = Font::new(&Path::new("media/1942_report/1942.ttf"), 50);
+
+    let eye = Point3::new(0.0, 2.0, 50.0);
+    let at = Point3::origin();
+    let mut arc_ball = ArcBall::new(eye, at);
+
+    window.set_light(Light::StickToCamera);
+
+    let wireframe_material = Rc::new(RefCell::new(Box::new(WireframeMaterial::new()) as
+        Box<Material + 'static>));
+
+    let rot = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.001);
+
+    let mut grp = window.add_group();
+    let mut planet_node: Option<SceneNode> = None;
+
+    let visualization_types = [Visualization::Dual,
+                               Visualization::Plates,
+                               Visualization::Boundaries,
+                               Visualization::Temperature,
+                               Visualization::Biomes];
+    let mut visualization_index = 0;
+    let mut regenerate_mesh = true;
+    // Plate changes leave the climate and drainage to be recomputed with the next mesh
+    let mut surface_stale = false;
+    let mut use_wireframe = true;
+    let mut show_rivers = false;
+    let mut show_currents = false;
+    let mut rotate = false;
+    let mut current_level = 0;
+    let mut num_tiles = 0;
+    let mut max_depth = 0.0;
+    let mut max_elevation = 0.0;
+
+    while window.render_with_camera(&mut arc_ball) {
+        if let Some(ref gen) = generator {
+            current_level = gen.current_level();
+        }
+        if let Some(ref pla) = planet {
+            num_tiles = pla.num_tiles();
+            let (d, e) = pla.get_elevation_scale();
+            max_depth = d;
+            max_elevation = e;
+        }
+        window.draw_text(&format!("Level: {}\nTiles: {}", current_level, num_tiles),
+                         &Point2::new(50.0, 50.0),
+                         &font,
+                         &Point3::new(1.0, 1.0, 1.0));
+
+        window.draw_text(&format!("Depth: {:.0}\nElevation: {:.0}", max_depth, max_elevation),
+                         &Point2::new(50.0, 1600.0),
+                         &font,
+                         &Point3::new(1.0, 1.0, 1.0));
+
+        for mut event in window.events().iter() {
+            match event.value {
+                /*
+                WindowEvent::Key(Key::Space, _, Action::Release, _) => {
+                    if let Some(ref mut gen) = generator {
+                        info!("Subdividing a level {} terrain", gen.current_level());
+                        let sw = Stopwatch::start_new();
+                        gen.subdivide();
+                        planet = None;
+                        info!("Subdivision took {} ms", sw.elapsed_ms());
+                        // (1744 ms, lvl 6), (7401 ms, lvl 7)
+                        regenerate_mesh = true;
+                        event.inhibited = true
+                    }
+                }
+                WindowEvent::Key(Key::A, _, Action::Release, _) => {
+                    if let Some(ref mut gen) = generator {
+                        let mut total_distortion =
+                            (gen.num_edges() as f32 * topology_distortion_rate).ceil() as u32;
+                        let mut iterations = 6;
+                        while iterations > 0 {
+                            let iteration_distortion = total_distortion / iterations;
+                            total_distortion -= iteration_distortion;
+                            gen.distort(iteration_distortion);
+                            gen.relax(0.);
+                            iterations -= 1;
+                        }
+                        planet = None;
+                        regenerate_mesh = true;
+                        event.inhibited = true;
+                    }
+                }
+                WindowEvent::Key(Key::S, _, Action::Release, _) => {
+                    if let Some(ref mut gen) = generator {
+                        let max_relax = 300;
+                        let mut last_move = f32::MAX;
+                        let mut i = 1;
+                        let num_nodes = gen.num_nodes() as f32;
+                        let average_node_radius = (f32::consts::PI * 4.0 / num_nodes).sqrt();
+                        let min_shift_delta = average_node_radius * num_nodes / 500000.0;
+                        while i <= max_relax {
+                            let rel = gen.relax(0.5);
+                            debug!("Relaxation iteration {}: {}", i, rel);
+                            let diff = (last_move - rel).abs();
+                            if diff < min_shift_delta {
+                                debug!("Relaxation converging with diff {}", diff);
+                                break;
+                            }
+                            last_move = rel;
+                            i += 1;
+                        }
+                        planet = None;
+                        regenerate_mesh = true;
+                        event.inhibited = true
+                    }
+                }
+                */
+                WindowEvent::Key(Key::D, _, Action::Release, _) => {
+                    visualization_index = (visualization_index + 1) % visualization_types.len();
+                    regenerate_mesh = true;
+                    event.inhibited = true
+                }
+                WindowEvent::Key(Key::R, _, Action::Release, _) => {
+                    rotate = !rotate;
+                    event.inhibited = true
+                }
+                WindowEvent::Key(Key::W, _, Action::Release, _) => {
+                    use_wireframe = !use_wireframe;
+                    regenerate_mesh = true;
+                    event.inhibited = true;
+                }
+                WindowEvent::Key(Key::H, _, Action::Release, _) => {
+                    show_rivers = !show_rivers;
+                    regenerate_mesh = true;
+                    event.inhibited = true;
+                }
+                WindowEvent::Key(Key::C, _, Action::Release, _) => {
+                    show_currents = !show_currents;
+                    regenerate_mesh = true;
+                    event.inhibited = true;
+                }
+                WindowEvent::Key(Key::G, _, Action::Release, _) => {
+                    if let Some(ref mut pla) = planet {
+                        pla.grow_plates();
+                        surface_stale = true;
+                        regenerate_mesh = true;
+                        event.inhibited = true;
+                    }
+                }
+                WindowEvent::Key(Key::M, _, Action::Release, _) => {
+                    if let Some(ref mut pla) = planet {
+                        let merges = pla.merge_plates();
+                        info!("Merged {} plates, {} left", merges.len(), pla.num_plates());
+                        surface_stale = true;
+                        regenerate_mesh = true;
+                        event.inhibited = true;
+                    }
+                }
+                WindowEvent::Key(Key::T, _, Action::Release, _) => {
+                    if let Some(ref mut pla) = planet {
+                        let dt = pla.default_time_step();
+                        let report = pla.step(dt);
+                        info!("Moved plates: {} tiles transferred, {} created, {} consumed",
+                              report.transferred,
+                              report.created,
+                              report.consumed);
+                        surface_stale = true;
+                        regenerate_mesh = true;
+                        event.inhibited = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if regenerate_mesh {
+            // Both are None while a mesh is being generated
+            let p = match planet.take() {
+                Some(p) => Some(p),
+                None => {
+                    generator.as_ref().map(|gen| {
+                        let mut p = gen.to_planet_with(plate_config);
+                        // The surface itself is computed with the mesh, off this thread
+                        p.set_sea_level(sea_level);
+                        p.set_climate_config(climate_config);
+                        p.set_biome_rules(biome_rules.clone());
+                        surface_stale = true;
+                        p
+                    })
+                }
+            };
+            if let Some(p) = p {
+                generate(visualization_types[visualization_index],
+                         generator.take(),
+                         p,
+                         use_wireframe,
+                         surface_stale,
+                         &tx);
+                surface_stale = false;
+            }
+            regenerate_mesh = false;
+        }
+        match rx.try_recv() {
+            Ok(Message::Complete(vertices, faces, normals, texcoords, terr, pla)) => {
+                if let Some(mut c) = planet_node {
+                    window.remove(&mut c);
+                }
+                let (wirecoords, wirefaces) = if show_rivers {
+                    generate_rivers(&pla)
+                } else if show_currents {
+                    generate_currents(&pla)
+                } else {
+                    generate_plate_vectors(&pla)
+                };
+                let line_colour = if show_rivers {
+                    Point3::new(0.2, 0.5, 1.0)
+                } else if show_currents {
+                    Point3::new(0.4, 0.9, 0.9)
+                } else {
+                    Point3::new(1.0, 1.0, 1.0)
+                };
+                planet_node = Some(add_mesh(visualization_types[visualization_index],
+                                            &mut grp,
+                                            vertices,
+                                            faces,
+                                            normals,
+                                            texcoords,
+                                            Some((wirecoords, wirefaces, line_colour)),
+                                            wireframe_material.clone()));
+                generator = terr;
+                planet = Some(pla);
+            }
+            _ => {}
+        }
+        if rotate {
+            grp.prepend_to_local_rotation(&rot);
+        }
+    }
+}
+
+fn add_mesh(visualization: Visualization,
+            parent: &mut SceneNode,
+            vertices: Vec<Point3<f32>>,
+            faces: Vec<Point3<u32>>,
+            normals: Option<Vec<Vector3<f32>>>,
+            texcoords: Option<Vec<Point2<f32>>>,
+            wireframes: Option<(Vec<Point3<f32>>, Vec<Point3<u32>>, Point3<f32>)>,
+            wireframe_material: Rc<RefCell<Box<Material + 'static>>>)
+            -> SceneNode {
+    let mut grp = parent.add_group();
+    if let Some((line_verts, line_faces, colour)) = wireframes {
+        let mesh = Mesh::new(line_verts, line_faces, None, None, false);
+        let mesh = Rc::new(RefCell::new(mesh));
+        let scale = 1.001;
+        let mut c = grp.add_mesh(mesh, Vector3::new(scale, scale, scale));
+
+        c.set_color(colour.x, colour.y, colour.z);
+        c.set_lines_width(2.0);
+        c.set_material(wireframe_material);
+    }
+    let mesh = Mesh::new(vertices, faces, normals, texcoords, false);
+    let mesh = Rc::new(RefCell::new(mesh));
+
+    let mut c = grp.add_mesh(mesh.clone(), Vector3::new(1.0, 1.0, 1.0));
+
+    c.set_color(1.0, 1.0, 1.0);
+    if visualization == Visualization::Plates || visualization == Visualization::Boundaries {
+        c.set_texture_from_file(&Path::new("media/groups.png"), "groups");
+    } else if visualization == Visualization::Temperature {
+        c.set_texture_from_file(&Path::new("media/temperature.png"), "temperature");
+    } else if visualization == Visualization::Biomes {
+        c.set_texture_from_file(&Path::new("media/biomes.png"), "biomes");
+    } else {
+        c.set_texture_from_file(&Path::new("media/elevation.png"), "elevation");
+    }
+    c.enable_backface_culling(true);
+
+    grp
+}
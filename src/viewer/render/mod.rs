@@ -0,0 +1,3 @@
+pub use viewer::render::wireframe_material::WireframeMaterial;
+
+mod wireframe_material;